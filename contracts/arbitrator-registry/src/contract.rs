@@ -0,0 +1,309 @@
+use cosmwasm_std::{
+    entry_point, from_binary, to_binary, Addr, Binary, CosmosMsg, Deps, DepsMut, Empty, Env,
+    MessageInfo, Order, Response, StdResult, Storage, SubMsg, Uint128, WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+
+use localterra_protocol::arbitrator_registry::{
+    Arbitrator, Config, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg,
+};
+use localterra_protocol::currencies::FiatCurrency;
+use localterra_protocol::factory_util::get_factory_config;
+
+use crate::errors::ArbitratorRegistryError;
+use crate::state::{ACTIVE_ARBITRATORS, ARBITRATORS, CONFIG};
+
+const SLASH_PERCENT: u128 = 10;
+// Roughly two weeks, longer than a trade, its dispute and appeal windows and the governance vote.
+const UNBONDING_PERIOD: u64 = 201_600;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ArbitratorRegistryError> {
+    CONFIG.save(
+        deps.storage,
+        &Config {
+            factory_addr: info.sender,
+            min_stake: msg.min_stake,
+        },
+    )?;
+    let res = Response::new()
+        .add_attribute("action", "instantiate_arbitrator_registry")
+        .add_attribute("min_stake", msg.min_stake);
+    Ok(res)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ArbitratorRegistryError> {
+    match msg {
+        ExecuteMsg::Receive(cw20_msg) => receive_cw20(deps, info, cw20_msg),
+        ExecuteMsg::Activate { arbitrator } => activate(deps, info, arbitrator),
+        ExecuteMsg::Deactivate { arbitrator } => deactivate(deps, env, info, arbitrator),
+        ExecuteMsg::Unstake {} => unstake(deps, env, info),
+        ExecuteMsg::Slash { arbitrator } => slash(deps, env, info, arbitrator),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => to_binary(&CONFIG.load(deps.storage)?),
+        QueryMsg::Arbitrator { address } => {
+            let address = deps.api.addr_validate(address.as_str())?;
+            to_binary(&ARBITRATORS.may_load(deps.storage, &address)?)
+        }
+        QueryMsg::ActiveArbitrators { fiat_currency } => {
+            to_binary(&query_active_arbitrators(deps, fiat_currency)?)
+        }
+    }
+}
+
+fn query_active_arbitrators(deps: Deps, fiat_currency: FiatCurrency) -> StdResult<Vec<Addr>> {
+    let result = ACTIVE_ARBITRATORS
+        .prefix(&fiat_currency.to_string())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .map(|key| Addr::unchecked(String::from_utf8(key).unwrap()))
+        .collect();
+    Ok(result)
+}
+
+// Stakes LOCAL for the cw20 sender and (re)sets the currencies they arbitrate.
+fn receive_cw20(
+    deps: DepsMut,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ArbitratorRegistryError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+    if info.sender != factory_cfg.token_addr {
+        return Err(ArbitratorRegistryError::Unauthorized {});
+    }
+
+    match from_binary(&cw20_msg.msg)? {
+        Cw20HookMsg::Register { fiat_currencies } => {
+            let addr = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            register(deps, addr, cw20_msg.amount, fiat_currencies)
+        }
+    }
+}
+
+fn register(
+    deps: DepsMut,
+    addr: Addr,
+    amount: Uint128,
+    fiat_currencies: Vec<FiatCurrency>,
+) -> Result<Response, ArbitratorRegistryError> {
+    if fiat_currencies.is_empty() {
+        return Err(ArbitratorRegistryError::NoCurrencies {});
+    }
+
+    let mut arbitrator = ARBITRATORS
+        .may_load(deps.storage, &addr)?
+        .unwrap_or(Arbitrator {
+            addr: addr.clone(),
+            fiat_currencies: vec![],
+            stake: Uint128::zero(),
+            active: false,
+            unbonding_height: None,
+        });
+
+    if arbitrator.active {
+        set_active_index(deps.storage, &arbitrator, false)?;
+    }
+    arbitrator.stake += amount;
+    arbitrator.fiat_currencies = fiat_currencies;
+    if arbitrator.active {
+        set_active_index(deps.storage, &arbitrator, true)?;
+    }
+    ARBITRATORS.save(deps.storage, &addr, &arbitrator)?;
+
+    let res = Response::new()
+        .add_attribute("action", "register_arbitrator")
+        .add_attribute("arbitrator", addr)
+        .add_attribute("stake", arbitrator.stake);
+    Ok(res)
+}
+
+// Only governance can activate arbitrators, either governance or the arbitrator can deactivate.
+fn activate(
+    deps: DepsMut,
+    info: MessageInfo,
+    arbitrator: String,
+) -> Result<Response, ArbitratorRegistryError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+    if info.sender != factory_cfg.gov_addr {
+        return Err(ArbitratorRegistryError::Unauthorized {});
+    }
+
+    let mut arbitrator = load_arbitrator(deps.as_ref(), arbitrator)?;
+    if arbitrator.stake < cfg.min_stake {
+        return Err(ArbitratorRegistryError::InsufficientStake {
+            stake: arbitrator.stake,
+            min_stake: cfg.min_stake,
+        });
+    }
+
+    if !arbitrator.active {
+        arbitrator.active = true;
+        arbitrator.unbonding_height = None;
+        set_active_index(deps.storage, &arbitrator, true)?;
+        ARBITRATORS.save(deps.storage, &arbitrator.addr, &arbitrator)?;
+    }
+
+    let res = Response::new()
+        .add_attribute("action", "activate_arbitrator")
+        .add_attribute("arbitrator", arbitrator.addr);
+    Ok(res)
+}
+
+// Deactivated arbitrators keep their stake bonded until the trades they may rule on are settled.
+fn deactivate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    arbitrator: String,
+) -> Result<Response, ArbitratorRegistryError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+
+    let mut arbitrator = load_arbitrator(deps.as_ref(), arbitrator)?;
+    if (info.sender != factory_cfg.gov_addr) & (info.sender != arbitrator.addr) {
+        return Err(ArbitratorRegistryError::Unauthorized {});
+    }
+
+    if arbitrator.active {
+        arbitrator.active = false;
+        arbitrator.unbonding_height = Some(env.block.height + UNBONDING_PERIOD);
+        set_active_index(deps.storage, &arbitrator, false)?;
+        ARBITRATORS.save(deps.storage, &arbitrator.addr, &arbitrator)?;
+    }
+
+    let res = Response::new()
+        .add_attribute("action", "deactivate_arbitrator")
+        .add_attribute("arbitrator", arbitrator.addr);
+    Ok(res)
+}
+
+fn unstake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ArbitratorRegistryError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+
+    let mut arbitrator = load_arbitrator(deps.as_ref(), info.sender.to_string())?;
+    if arbitrator.stake.is_zero() {
+        return Err(ArbitratorRegistryError::NoStake {
+            arbitrator: arbitrator.addr,
+        });
+    }
+    if arbitrator.active {
+        return Err(ArbitratorRegistryError::ArbitratorActive {
+            arbitrator: arbitrator.addr,
+        });
+    }
+    if let Some(unbonding_height) = arbitrator.unbonding_height {
+        if env.block.height < unbonding_height {
+            return Err(ArbitratorRegistryError::Unbonding { unbonding_height });
+        }
+    }
+    // The entry stays with no stake, so late slashes of its rulings don't fail the appeal payout
+    let amount = arbitrator.stake;
+    arbitrator.stake = Uint128::zero();
+    arbitrator.unbonding_height = None;
+    ARBITRATORS.save(deps.storage, &arbitrator.addr, &arbitrator)?;
+
+    let transfer_msg = SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: factory_cfg.token_addr.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: arbitrator.addr.to_string(),
+            amount,
+        })?,
+        funds: vec![],
+    }));
+
+    let res = Response::new()
+        .add_submessage(transfer_msg)
+        .add_attribute("action", "unstake")
+        .add_attribute("arbitrator", arbitrator.addr)
+        .add_attribute("amount", amount);
+    Ok(res)
+}
+
+// Governance slashes arbitrators whose ruling was overturned on appeal, unbonding stake included.
+fn slash(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    arbitrator: String,
+) -> Result<Response, ArbitratorRegistryError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+    if info.sender != factory_cfg.gov_addr {
+        return Err(ArbitratorRegistryError::Unauthorized {});
+    }
+
+    let mut arbitrator = load_arbitrator(deps.as_ref(), arbitrator)?;
+
+    let amount = arbitrator.stake.multiply_ratio(SLASH_PERCENT, 100u128);
+    arbitrator.stake -= amount;
+    if arbitrator.active & (arbitrator.stake < cfg.min_stake) {
+        arbitrator.active = false;
+        arbitrator.unbonding_height = Some(env.block.height + UNBONDING_PERIOD);
+        set_active_index(deps.storage, &arbitrator, false)?;
+    }
+    ARBITRATORS.save(deps.storage, &arbitrator.addr, &arbitrator)?;
+
+    let mut res = Response::new();
+    if !amount.is_zero() {
+        res = res.add_submessage(SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: factory_cfg.token_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: factory_cfg.gov_addr.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        })));
+    }
+    let res = res
+        .add_attribute("action", "slash_arbitrator")
+        .add_attribute("arbitrator", arbitrator.addr)
+        .add_attribute("amount", amount)
+        .add_attribute("active", arbitrator.active.to_string());
+    Ok(res)
+}
+
+fn load_arbitrator(deps: Deps, arbitrator: String) -> Result<Arbitrator, ArbitratorRegistryError> {
+    let addr = deps.api.addr_validate(arbitrator.as_str())?;
+    ARBITRATORS
+        .may_load(deps.storage, &addr)?
+        .ok_or(ArbitratorRegistryError::ArbitratorNotFound { arbitrator: addr })
+}
+
+fn set_active_index(
+    storage: &mut dyn Storage,
+    arbitrator: &Arbitrator,
+    active: bool,
+) -> StdResult<()> {
+    for fiat_currency in arbitrator.fiat_currencies.iter() {
+        let key = (fiat_currency.to_string(), &arbitrator.addr);
+        let key = (key.0.as_str(), key.1);
+        if active {
+            ACTIVE_ARBITRATORS.save(storage, key, &Empty {})?;
+        } else {
+            ACTIVE_ARBITRATORS.remove(storage, key);
+        }
+    }
+    Ok(())
+}
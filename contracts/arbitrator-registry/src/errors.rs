@@ -0,0 +1,22 @@
+use cosmwasm_std::{Addr, StdError, Uint128};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ArbitratorRegistryError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+    #[error("Unauthorized.")]
+    Unauthorized {},
+    #[error("Arbitrator is not registered.")]
+    ArbitratorNotFound { arbitrator: Addr },
+    #[error("Arbitrators must register for at least one currency.")]
+    NoCurrencies {},
+    #[error("Arbitrator stake is below the minimum.")]
+    InsufficientStake { stake: Uint128, min_stake: Uint128 },
+    #[error("Active arbitrators can't unstake.")]
+    ArbitratorActive { arbitrator: Addr },
+    #[error("Arbitrator has no stake to withdraw.")]
+    NoStake { arbitrator: Addr },
+    #[error("Stake is still unbonding.")]
+    Unbonding { unbonding_height: u64 },
+}
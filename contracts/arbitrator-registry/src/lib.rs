@@ -0,0 +1,5 @@
+pub mod contract;
+mod errors;
+mod mock_querier;
+mod state;
+mod tests;
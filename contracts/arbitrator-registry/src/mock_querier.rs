@@ -0,0 +1,67 @@
+#![cfg(test)]
+use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    from_slice, to_binary, Addr, ContractResult, Empty, OwnedDeps, Querier, QuerierResult,
+    QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
+};
+use localterra_protocol::bitcoin::BtcNetwork;
+use localterra_protocol::factory::Config as FactoryConfig;
+
+/// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
+/// this uses our CustomQuerier.
+pub fn mock_dependencies() -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier> {
+    OwnedDeps {
+        api: MockApi::default(),
+        storage: MockStorage::default(),
+        querier: WasmMockQuerier {
+            base: MockQuerier::new(&[(MOCK_CONTRACT_ADDR, &[])]),
+        },
+    }
+}
+
+pub struct WasmMockQuerier {
+    base: MockQuerier<Empty>,
+}
+
+impl Querier for WasmMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<Empty> = match from_slice(bin_request) {
+            Ok(v) => v,
+            Err(e) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        self.handle_query(&request)
+    }
+}
+
+impl WasmMockQuerier {
+    pub fn handle_query(&self, request: &QueryRequest<Empty>) -> QuerierResult {
+        match &request {
+            // The factory config is the only contract query made by the registry
+            QueryRequest::Wasm(WasmQuery::Smart { .. }) => {
+                SystemResult::Ok(ContractResult::from(to_binary(&FactoryConfig {
+                    trade_code_id: 0,
+                    token_addr: Addr::unchecked("local"),
+                    local_ust_pool_addr: Addr::unchecked("local-ust"),
+                    terraswap_factory_addr: Addr::unchecked("terraswap-factory"),
+                    gov_addr: Addr::unchecked("gov"),
+                    offers_addr: Addr::unchecked("offers"),
+                    fee_collector_addr: Addr::unchecked("fee-collector"),
+                    trading_incentives_addr: Addr::unchecked("trading-incentives"),
+                    arbitrator_registry_addr: Addr::unchecked("arbitrator-registry"),
+                    btc_relay_addr: Addr::unchecked("btc-relay"),
+                    dispute_deposit: Uint128::new(1_000_000),
+                    appeal_bond: Uint128::new(5_000_000),
+                    btc_pow_limit: 0x207f_ffff,
+                    btc_confirmations: 3,
+                    btc_network: BtcNetwork::Regtest,
+                })))
+            }
+            _ => self.base.handle_query(request),
+        }
+    }
+}
@@ -0,0 +1,8 @@
+use cosmwasm_std::{Addr, Empty};
+use cw_storage_plus::{Item, Map};
+use localterra_protocol::arbitrator_registry::{Arbitrator, Config};
+
+pub const CONFIG: Item<Config> = Item::new("config");
+pub const ARBITRATORS: Map<&Addr, Arbitrator> = Map::new("arbitrators");
+// Active arbitrators by fiat currency
+pub const ACTIVE_ARBITRATORS: Map<(&str, &Addr), Empty> = Map::new("active_arbitrators");
@@ -0,0 +1,384 @@
+#![cfg(test)]
+use cosmwasm_std::testing::{mock_env, mock_info};
+use cosmwasm_std::{
+    attr, from_binary, to_binary, Addr, CosmosMsg, Deps, DepsMut, SubMsg, Uint128, WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+
+use localterra_protocol::arbitrator_registry::{
+    Arbitrator, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg,
+};
+use localterra_protocol::currencies::FiatCurrency;
+
+use crate::contract::{execute, instantiate, query};
+use crate::errors::ArbitratorRegistryError;
+use crate::mock_querier::mock_dependencies;
+
+fn register_msg(sender: &str, amount: u128, fiat_currencies: Vec<FiatCurrency>) -> ExecuteMsg {
+    ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount: Uint128::new(amount),
+        msg: to_binary(&Cw20HookMsg::Register { fiat_currencies }).unwrap(),
+    })
+}
+
+fn do_init(deps: DepsMut) {
+    let msg = InstantiateMsg {
+        min_stake: Uint128::new(100),
+    };
+    instantiate(deps, mock_env(), mock_info("factory", &[]), msg).unwrap();
+}
+
+fn active_arbitrators(deps: Deps, fiat_currency: FiatCurrency) -> Vec<Addr> {
+    from_binary(
+        &query(
+            deps,
+            mock_env(),
+            QueryMsg::ActiveArbitrators { fiat_currency },
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn register_and_activate() {
+    let mut deps = mock_dependencies();
+    do_init(deps.as_mut());
+
+    //Stake has to be sent through the LOCAL token
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other-token", &[]),
+        register_msg("arbitrator", 100, vec![FiatCurrency::BRL]),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::Unauthorized {}
+    ));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("local", &[]),
+        register_msg("arbitrator", 50, vec![FiatCurrency::BRL, FiatCurrency::COP]),
+    )
+    .unwrap();
+
+    //Registered arbitrators are inactive until governance activates them
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::BRL).len(),
+        0
+    );
+    let activate_msg = ExecuteMsg::Activate {
+        arbitrator: "arbitrator".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbitrator", &[]),
+        activate_msg.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::Unauthorized {}
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gov", &[]),
+        activate_msg.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::InsufficientStake { .. }
+    ));
+
+    //Topping up the stake
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("local", &[]),
+        register_msg("arbitrator", 50, vec![FiatCurrency::BRL, FiatCurrency::COP]),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gov", &[]),
+        activate_msg,
+    )
+    .unwrap();
+
+    let arbitrator: Option<Arbitrator> = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Arbitrator {
+                address: "arbitrator".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let arbitrator = arbitrator.unwrap();
+    assert_eq!(arbitrator.stake, Uint128::new(100));
+    assert!(arbitrator.active);
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::BRL),
+        vec![Addr::unchecked("arbitrator")]
+    );
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::COP),
+        vec![Addr::unchecked("arbitrator")]
+    );
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::USD).len(),
+        0
+    );
+
+    //Changing currencies updates the active index
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("local", &[]),
+        register_msg("arbitrator", 0, vec![FiatCurrency::USD]),
+    )
+    .unwrap();
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::BRL).len(),
+        0
+    );
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::USD).len(),
+        1
+    );
+}
+
+#[test]
+fn deactivate_and_unstake() {
+    let mut deps = mock_dependencies();
+    do_init(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("local", &[]),
+        register_msg("arbitrator", 100, vec![FiatCurrency::BRL]),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gov", &[]),
+        ExecuteMsg::Activate {
+            arbitrator: "arbitrator".to_string(),
+        },
+    )
+    .unwrap();
+
+    //Active arbitrators can't withdraw their stake
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbitrator", &[]),
+        ExecuteMsg::Unstake {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::ArbitratorActive { .. }
+    ));
+
+    //Only governance or the arbitrator can deactivate
+    let deactivate_msg = ExecuteMsg::Deactivate {
+        arbitrator: "arbitrator".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other", &[]),
+        deactivate_msg.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::Unauthorized {}
+    ));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbitrator", &[]),
+        deactivate_msg,
+    )
+    .unwrap();
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::BRL).len(),
+        0
+    );
+
+    //The stake stays bonded while the arbitrator's trades can still be appealed
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbitrator", &[]),
+        ExecuteMsg::Unstake {},
+    );
+    let unbonding_height = mock_env().block.height + 201_600;
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::Unbonding { unbonding_height: h } if h == unbonding_height
+    ));
+
+    let mut env = mock_env();
+    env.block.height = unbonding_height;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("arbitrator", &[]),
+        ExecuteMsg::Unstake {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "local".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "arbitrator".to_string(),
+                amount: Uint128::new(100),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))]
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("arbitrator", &[]),
+        ExecuteMsg::Unstake {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::NoStake { .. }
+    ));
+
+    //An appeal resolved after the arbitrator unstaked still goes through, with nothing to slash
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("gov", &[]),
+        ExecuteMsg::Slash {
+            arbitrator: "arbitrator".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 0);
+    assert_eq!(res.attributes[2], attr("amount", "0"));
+}
+
+#[test]
+fn slash() {
+    let mut deps = mock_dependencies();
+    do_init(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("local", &[]),
+        register_msg("arbitrator", 105, vec![FiatCurrency::BRL]),
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gov", &[]),
+        ExecuteMsg::Activate {
+            arbitrator: "arbitrator".to_string(),
+        },
+    )
+    .unwrap();
+
+    let slash_msg = ExecuteMsg::Slash {
+        arbitrator: "arbitrator".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbitrator", &[]),
+        slash_msg.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::Unauthorized {}
+    ));
+
+    //10% of the stake goes to governance and the arbitrator drops below the minimum
+    let res = execute(deps.as_mut(), mock_env(), mock_info("gov", &[]), slash_msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "local".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "gov".to_string(),
+                amount: Uint128::new(10),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))]
+    );
+    let arbitrator: Option<Arbitrator> = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Arbitrator {
+                address: "arbitrator".to_string(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let arbitrator = arbitrator.unwrap();
+    assert_eq!(arbitrator.stake, Uint128::new(95));
+    assert!(!arbitrator.active);
+    assert_eq!(
+        active_arbitrators(deps.as_ref(), FiatCurrency::BRL).len(),
+        0
+    );
+
+    //Dropping below the minimum starts the unbonding, the stake can still be slashed meanwhile
+    assert_eq!(
+        arbitrator.unbonding_height,
+        Some(mock_env().block.height + 201_600)
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("arbitrator", &[]),
+        ExecuteMsg::Unstake {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::Unbonding { .. }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gov", &[]),
+        ExecuteMsg::Slash {
+            arbitrator: "arbitrator".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+
+    //Slashing an unknown arbitrator fails instead of taking nothing
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("gov", &[]),
+        ExecuteMsg::Slash {
+            arbitrator: "other".to_string(),
+        },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        ArbitratorRegistryError::ArbitratorNotFound { .. }
+    ));
+}
@@ -0,0 +1,243 @@
+use std::convert::TryFrom;
+
+use cosmwasm_std::{
+    entry_point, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    Storage, Uint256, Uint512,
+};
+use cw_storage_plus::U32Key;
+
+use localterra_protocol::bitcoin::{
+    bits_to_target, parse_display_hash, target_to_bits, target_to_work, to_display_hash,
+    BlockHeader, RETARGET_INTERVAL, TARGET_TIMESPAN,
+};
+use localterra_protocol::btc_relay::{Config, ExecuteMsg, InstantiateMsg, QueryMsg, StoredHeader};
+
+use crate::errors::BtcRelayError;
+use crate::state::{BEST_CHAIN, CONFIG, HEADERS, TIP};
+
+// Twice the 10 minute block spacing
+const MIN_DIFFICULTY_DELAY: u32 = 20 * 60;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, BtcRelayError> {
+    if !msg.no_retargeting & (msg.checkpoint_height % RETARGET_INTERVAL != 0) {
+        return Err(BtcRelayError::InvalidCheckpoint {
+            height: msg.checkpoint_height,
+        });
+    }
+    bits_to_target(msg.pow_limit)?;
+    let header = BlockHeader::parse(msg.checkpoint.as_slice())?;
+    let checkpoint = StoredHeader {
+        hash: Binary::from(header.hash.to_vec()),
+        prev_block_hash: Binary::from(header.prev_block_hash.to_vec()),
+        height: msg.checkpoint_height,
+        time: header.time,
+        bits: header.bits,
+        period_start_time: header.time,
+        chain_work: target_to_work(header.target()?),
+    };
+
+    CONFIG.save(
+        deps.storage,
+        &Config {
+            factory_addr: info.sender,
+            pow_limit: msg.pow_limit,
+            no_retargeting: msg.no_retargeting,
+            min_difficulty_blocks: msg.min_difficulty_blocks,
+        },
+    )?;
+    HEADERS.save(deps.storage, &header.hash, &checkpoint)?;
+    BEST_CHAIN.save(
+        deps.storage,
+        U32Key::from(checkpoint.height),
+        &checkpoint.hash,
+    )?;
+    TIP.save(deps.storage, &checkpoint.hash)?;
+
+    let res = Response::new()
+        .add_attribute("action", "instantiate_btc_relay")
+        .add_attribute("checkpoint", header.display_hash())
+        .add_attribute("height", checkpoint.height.to_string());
+    Ok(res)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, BtcRelayError> {
+    match msg {
+        ExecuteMsg::SubmitHeaders { headers } => submit_headers(deps, headers),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => to_binary(&CONFIG.load(deps.storage)?),
+        QueryMsg::Tip {} => to_binary(&load_tip(deps.storage)?),
+        QueryMsg::Header { block_hash } => {
+            let hash = parse_block_hash(&block_hash)?;
+            to_binary(&HEADERS.may_load(deps.storage, &hash)?)
+        }
+        QueryMsg::Confirmations { block_hash } => {
+            to_binary(&query_confirmations(deps, block_hash)?)
+        }
+    }
+}
+
+fn query_confirmations(deps: Deps, block_hash: String) -> StdResult<u32> {
+    let hash = parse_block_hash(&block_hash)?;
+    let header = match HEADERS.may_load(deps.storage, &hash)? {
+        Some(header) => header,
+        None => return Ok(0),
+    };
+    let best = BEST_CHAIN.may_load(deps.storage, U32Key::from(header.height))?;
+    if best != Some(header.hash) {
+        return Ok(0);
+    }
+    let tip = load_tip(deps.storage)?;
+    Ok(tip.height - header.height + 1)
+}
+
+// Anyone can relay headers, only the proof of work makes them count.
+fn submit_headers(deps: DepsMut, headers: Vec<Binary>) -> Result<Response, BtcRelayError> {
+    if headers.is_empty() {
+        return Err(BtcRelayError::NoHeaders {});
+    }
+    let cfg = CONFIG.load(deps.storage)?;
+    let mut tip = load_tip(deps.storage)?;
+    let mut accepted = 0;
+
+    for raw in headers {
+        let header = BlockHeader::parse(raw.as_slice())?;
+        if HEADERS.has(deps.storage, &header.hash) {
+            continue;
+        }
+        let parent = HEADERS
+            .may_load(deps.storage, &header.prev_block_hash)?
+            .ok_or_else(|| BtcRelayError::UnknownParent {
+                prev_block_hash: to_display_hash(&header.prev_block_hash),
+            })?;
+        let height = parent.height + 1;
+
+        let expected = expected_bits(deps.storage, &cfg, &parent, &header, height)?;
+        if header.bits != expected {
+            return Err(BtcRelayError::InvalidDifficulty {
+                expected,
+                bits: header.bits,
+            });
+        }
+        if !header.check_pow()? {
+            return Err(BtcRelayError::InsufficientWork {
+                block_hash: header.display_hash(),
+            });
+        }
+
+        let period_start_time = if height % RETARGET_INTERVAL == 0 {
+            header.time
+        } else {
+            parent.period_start_time
+        };
+        let stored = StoredHeader {
+            hash: Binary::from(header.hash.to_vec()),
+            prev_block_hash: Binary::from(header.prev_block_hash.to_vec()),
+            height,
+            time: header.time,
+            bits: header.bits,
+            period_start_time,
+            chain_work: parent
+                .chain_work
+                .checked_add(target_to_work(header.target()?))
+                .map_err(StdError::from)?,
+        };
+        HEADERS.save(deps.storage, &header.hash, &stored)?;
+        accepted += 1;
+
+        // Ties keep the first chain seen
+        if stored.chain_work > tip.chain_work {
+            set_tip(deps.storage, &stored, &tip)?;
+            tip = stored;
+        }
+    }
+
+    let res = Response::new()
+        .add_attribute("action", "submit_headers")
+        .add_attribute("accepted", accepted.to_string())
+        .add_attribute("tip", to_display_hash(tip.hash.as_slice()))
+        .add_attribute("height", tip.height.to_string());
+    Ok(res)
+}
+
+// Difficulty only changes on retarget boundaries, by at most a factor of 4 each time.
+fn expected_bits(
+    storage: &dyn Storage,
+    cfg: &Config,
+    parent: &StoredHeader,
+    header: &BlockHeader,
+    height: u32,
+) -> Result<u32, BtcRelayError> {
+    if cfg.no_retargeting {
+        return Ok(parent.bits);
+    }
+    if height % RETARGET_INTERVAL != 0 {
+        if !cfg.min_difficulty_blocks {
+            return Ok(parent.bits);
+        }
+        if header.time > parent.time.saturating_add(MIN_DIFFICULTY_DELAY) {
+            return Ok(cfg.pow_limit);
+        }
+        // Otherwise the difficulty is the one of the last block not mined at the minimum
+        let mut last = parent.clone();
+        while (last.height % RETARGET_INTERVAL != 0) & (last.bits == cfg.pow_limit) {
+            last = HEADERS.load(storage, last.prev_block_hash.as_slice())?;
+        }
+        return Ok(last.bits);
+    }
+    let timespan = parent
+        .time
+        .saturating_sub(parent.period_start_time)
+        .max(TARGET_TIMESPAN / 4)
+        .min(TARGET_TIMESPAN * 4);
+    let pow_limit = bits_to_target(cfg.pow_limit)?;
+    let target = bits_to_target(parent.bits)?.full_mul(timespan) / Uint512::from(TARGET_TIMESPAN);
+    let target = Uint256::try_from(target)
+        .ok()
+        .filter(|target| *target <= pow_limit)
+        .unwrap_or(pow_limit);
+    Ok(target_to_bits(target))
+}
+
+// Moves the best chain index over to the branch ending at `new_tip`.
+fn set_tip(
+    storage: &mut dyn Storage,
+    new_tip: &StoredHeader,
+    old_tip: &StoredHeader,
+) -> Result<(), BtcRelayError> {
+    for height in new_tip.height + 1..=old_tip.height {
+        BEST_CHAIN.remove(storage, U32Key::from(height));
+    }
+    let mut header = new_tip.clone();
+    while BEST_CHAIN.may_load(storage, U32Key::from(header.height))? != Some(header.hash.clone()) {
+        BEST_CHAIN.save(storage, U32Key::from(header.height), &header.hash)?;
+        header = HEADERS.load(storage, header.prev_block_hash.as_slice())?;
+    }
+    TIP.save(storage, &new_tip.hash)?;
+    Ok(())
+}
+
+fn load_tip(storage: &dyn Storage) -> StdResult<StoredHeader> {
+    let tip = TIP.load(storage)?;
+    HEADERS.load(storage, tip.as_slice())
+}
+
+fn parse_block_hash(block_hash: &str) -> StdResult<[u8; 32]> {
+    parse_display_hash(block_hash).map_err(|err| StdError::generic_err(err.to_string()))
+}
@@ -0,0 +1,21 @@
+use cosmwasm_std::StdError;
+use localterra_protocol::errors::BitcoinError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BtcRelayError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+    #[error("{0}")]
+    Bitcoin(#[from] BitcoinError),
+    #[error("Checkpoint has to open a retarget period.")]
+    InvalidCheckpoint { height: u32 },
+    #[error("No headers submitted.")]
+    NoHeaders {},
+    #[error("Header doesn't build on a known block.")]
+    UnknownParent { prev_block_hash: String },
+    #[error("Header difficulty doesn't match the expected one.")]
+    InvalidDifficulty { expected: u32, bits: u32 },
+    #[error("Header hash is above its target.")]
+    InsufficientWork { block_hash: String },
+}
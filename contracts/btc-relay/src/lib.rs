@@ -0,0 +1,4 @@
+pub mod contract;
+mod errors;
+mod state;
+mod tests;
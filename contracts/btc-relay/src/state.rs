@@ -0,0 +1,10 @@
+use cosmwasm_std::Binary;
+use cw_storage_plus::{Item, Map, U32Key};
+use localterra_protocol::btc_relay::{Config, StoredHeader};
+
+pub const CONFIG: Item<Config> = Item::new("config");
+// All valid headers by hash, forks included
+pub const HEADERS: Map<&[u8], StoredHeader> = Map::new("headers");
+// Hashes of the heaviest chain by height
+pub const BEST_CHAIN: Map<U32Key, Binary> = Map::new("best_chain");
+pub const TIP: Item<Binary> = Item::new("tip");
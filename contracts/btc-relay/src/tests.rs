@@ -0,0 +1,230 @@
+#![cfg(test)]
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier};
+use cosmwasm_std::{from_binary, Binary, Deps, DepsMut, MemoryStorage, OwnedDeps};
+
+use localterra_protocol::bitcoin::{sha256d, to_display_hash, BlockHeader, RETARGET_INTERVAL};
+use localterra_protocol::btc_relay::{ExecuteMsg, InstantiateMsg, QueryMsg, StoredHeader};
+
+use crate::contract::{execute, instantiate, query};
+use crate::errors::BtcRelayError;
+
+const REGTEST_BITS: u32 = 0x207f_ffff;
+const CHECKPOINT_TIME: u32 = 1_571_797_500;
+
+// Builds a header on `prev`, grinding the nonce until it meets (or misses) its target
+fn mine(prev: &[u8], time: u32, bits: u32, valid: bool) -> Vec<u8> {
+    let mut raw = vec![];
+    raw.extend_from_slice(&0x2000_0000u32.to_le_bytes());
+    raw.extend_from_slice(prev);
+    raw.extend_from_slice(&sha256d(&time.to_le_bytes()));
+    raw.extend_from_slice(&time.to_le_bytes());
+    raw.extend_from_slice(&bits.to_le_bytes());
+    for nonce in 0u32.. {
+        raw.truncate(76);
+        raw.extend_from_slice(&nonce.to_le_bytes());
+        if BlockHeader::parse(&raw).unwrap().check_pow().unwrap() == valid {
+            break;
+        }
+    }
+    raw
+}
+
+fn mine_chain(prev: &[u8], start_time: u32, spacing: u32, count: u32) -> Vec<Vec<u8>> {
+    let mut headers: Vec<Vec<u8>> = vec![];
+    for i in 0..count {
+        let prev = headers
+            .last()
+            .map_or(prev.to_vec(), |header| sha256d(header).to_vec());
+        headers.push(mine(
+            &prev,
+            start_time + spacing * (i + 1),
+            REGTEST_BITS,
+            true,
+        ));
+    }
+    headers
+}
+
+fn do_init(deps: DepsMut, no_retargeting: bool) -> Vec<u8> {
+    let checkpoint = mine(&[0u8; 32], CHECKPOINT_TIME, REGTEST_BITS, true);
+    let msg = InstantiateMsg {
+        checkpoint: Binary::from(checkpoint.clone()),
+        checkpoint_height: 0,
+        pow_limit: REGTEST_BITS,
+        no_retargeting,
+        min_difficulty_blocks: false,
+    };
+    instantiate(deps, mock_env(), mock_info("factory", &[]), msg).unwrap();
+    checkpoint
+}
+
+fn submit(
+    deps: &mut OwnedDeps<MemoryStorage, MockApi, MockQuerier>,
+    headers: &[Vec<u8>],
+) -> Result<cosmwasm_std::Response, BtcRelayError> {
+    let msg = ExecuteMsg::SubmitHeaders {
+        headers: headers.iter().cloned().map(Binary::from).collect(),
+    };
+    execute(deps.as_mut(), mock_env(), mock_info("relayer", &[]), msg)
+}
+
+fn confirmations(deps: Deps, header: &[u8]) -> u32 {
+    let block_hash = to_display_hash(&sha256d(header));
+    from_binary(&query(deps, mock_env(), QueryMsg::Confirmations { block_hash }).unwrap()).unwrap()
+}
+
+fn tip(deps: Deps) -> StoredHeader {
+    from_binary(&query(deps, mock_env(), QueryMsg::Tip {}).unwrap()).unwrap()
+}
+
+#[test]
+fn submit_headers() {
+    let mut deps = mock_dependencies(&[]);
+    let checkpoint = do_init(deps.as_mut(), true);
+    let chain = mine_chain(&sha256d(&checkpoint), CHECKPOINT_TIME, 600, 3);
+
+    submit(&mut deps, &chain).unwrap();
+    assert_eq!(tip(deps.as_ref()).height, 3);
+    assert_eq!(confirmations(deps.as_ref(), &checkpoint), 4);
+    assert_eq!(confirmations(deps.as_ref(), &chain[0]), 3);
+    assert_eq!(confirmations(deps.as_ref(), &chain[2]), 1);
+    assert_eq!(confirmations(deps.as_ref(), &[0u8; 80]), 0);
+
+    //Headers have to build on a known block, at the right difficulty, with enough work
+    let orphan = mine(&[1u8; 32], CHECKPOINT_TIME, REGTEST_BITS, true);
+    let res = submit(&mut deps, &[orphan]);
+    assert!(matches!(
+        res.err().unwrap(),
+        BtcRelayError::UnknownParent { .. }
+    ));
+    let easier = mine(&sha256d(&chain[2]), CHECKPOINT_TIME, 0x2100_ffff, true);
+    let res = submit(&mut deps, &[easier]);
+    assert!(matches!(
+        res.err().unwrap(),
+        BtcRelayError::InvalidDifficulty {
+            expected: REGTEST_BITS,
+            bits: 0x2100_ffff
+        }
+    ));
+    let unworked = mine(&sha256d(&chain[2]), CHECKPOINT_TIME, REGTEST_BITS, false);
+    let res = submit(&mut deps, &[unworked]);
+    assert!(matches!(
+        res.err().unwrap(),
+        BtcRelayError::InsufficientWork { .. }
+    ));
+    let res = submit(&mut deps, &[vec![0u8; 79]]);
+    assert!(matches!(res.err().unwrap(), BtcRelayError::Bitcoin(_)));
+}
+
+#[test]
+fn heaviest_chain() {
+    let mut deps = mock_dependencies(&[]);
+    let checkpoint = do_init(deps.as_mut(), true);
+    let chain = mine_chain(&sha256d(&checkpoint), CHECKPOINT_TIME, 600, 3);
+    let fork = mine_chain(&sha256d(&checkpoint), CHECKPOINT_TIME, 601, 4);
+    submit(&mut deps, &chain).unwrap();
+
+    //A fork with as much work doesn't replace the chain seen first
+    submit(&mut deps, &fork[..3]).unwrap();
+    assert_eq!(
+        tip(deps.as_ref()).hash,
+        Binary::from(sha256d(&chain[2]).to_vec())
+    );
+    assert_eq!(confirmations(deps.as_ref(), &fork[0]), 0);
+
+    //One with more work does
+    submit(&mut deps, &fork[3..]).unwrap();
+    assert_eq!(tip(deps.as_ref()).height, 4);
+    assert_eq!(confirmations(deps.as_ref(), &chain[0]), 0);
+    assert_eq!(confirmations(deps.as_ref(), &chain[2]), 0);
+    assert_eq!(confirmations(deps.as_ref(), &fork[0]), 4);
+    assert_eq!(confirmations(deps.as_ref(), &checkpoint), 5);
+
+    //Known headers are skipped
+    let res = submit(&mut deps, &fork).unwrap();
+    assert_eq!(res.attributes[1].value, "0");
+}
+
+#[test]
+fn difficulty_retarget() {
+    let mut deps = mock_dependencies(&[]);
+    let msg = InstantiateMsg {
+        checkpoint: Binary::from(mine(&[0u8; 32], CHECKPOINT_TIME, REGTEST_BITS, true)),
+        checkpoint_height: 5,
+        pow_limit: REGTEST_BITS,
+        no_retargeting: false,
+        min_difficulty_blocks: false,
+    };
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("factory", &[]), msg);
+    assert!(matches!(
+        res.err().unwrap(),
+        BtcRelayError::InvalidCheckpoint { height: 5 }
+    ));
+
+    let checkpoint = do_init(deps.as_mut(), false);
+    // A period mined in 2.5 minute blocks, 4 times faster than the 10 minute target
+    let period = mine_chain(
+        &sha256d(&checkpoint),
+        CHECKPOINT_TIME,
+        150,
+        RETARGET_INTERVAL - 1,
+    );
+    submit(&mut deps, &period).unwrap();
+    let last = sha256d(period.last().unwrap());
+    let time = CHECKPOINT_TIME + 150 * RETARGET_INTERVAL;
+
+    //The next period has to be 4 times as hard
+    let res = submit(&mut deps, &[mine(&last, time, REGTEST_BITS, true)]);
+    assert!(matches!(
+        res.err().unwrap(),
+        BtcRelayError::InvalidDifficulty {
+            expected: 0x201f_ffff,
+            ..
+        }
+    ));
+    submit(&mut deps, &[mine(&last, time, 0x201f_ffff, true)]).unwrap();
+    let tip = tip(deps.as_ref());
+    assert_eq!(tip.height, RETARGET_INTERVAL);
+    assert_eq!(tip.period_start_time, time);
+}
+
+#[test]
+fn min_difficulty_blocks() {
+    let mut deps = mock_dependencies(&[]);
+    let bits = 0x201f_ffff;
+    let checkpoint = mine(&[0u8; 32], CHECKPOINT_TIME, bits, true);
+    let msg = InstantiateMsg {
+        checkpoint: Binary::from(checkpoint.clone()),
+        checkpoint_height: 0,
+        pow_limit: REGTEST_BITS,
+        no_retargeting: false,
+        min_difficulty_blocks: true,
+    };
+    instantiate(deps.as_mut(), mock_env(), mock_info("factory", &[]), msg).unwrap();
+    let first = mine(&sha256d(&checkpoint), CHECKPOINT_TIME + 600, bits, true);
+    let first_hash = sha256d(&first);
+    submit(&mut deps, &[first]).unwrap();
+
+    //The minimum difficulty is only allowed after 20 minutes without a block
+    let time = CHECKPOINT_TIME + 600 + 1200;
+    let res = submit(&mut deps, &[mine(&first_hash, time, REGTEST_BITS, true)]);
+    assert!(matches!(
+        res.err().unwrap(),
+        BtcRelayError::InvalidDifficulty { expected, .. } if expected == bits
+    ));
+    let easy = mine(&first_hash, time + 1, REGTEST_BITS, true);
+    let easy_hash = sha256d(&easy);
+    submit(&mut deps, &[easy]).unwrap();
+
+    //The next block goes back to the difficulty before it
+    let res = submit(
+        &mut deps,
+        &[mine(&easy_hash, time + 60, REGTEST_BITS, true)],
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        BtcRelayError::InvalidDifficulty { expected, .. } if expected == bits
+    ));
+    submit(&mut deps, &[mine(&easy_hash, time + 60, bits, true)]).unwrap();
+    assert_eq!(tip(deps.as_ref()).height, 3);
+}
@@ -6,6 +6,9 @@ use cosmwasm_std::{to_binary, CosmosMsg, DepsMut, Env, MessageInfo, Response, Su
 
 use crate::errors::FactoryError;
 use crate::state::CONFIG;
+use localterra_protocol::arbitrator_registry::InstantiateMsg as ArbitratorRegistryInstantiateMsg;
+use localterra_protocol::bitcoin::BtcNetwork;
+use localterra_protocol::btc_relay::InstantiateMsg as BtcRelayInstantiateMsg;
 use localterra_protocol::factory::{Config, ExecuteMsg, InstantiateMsg, QueryMsg};
 use localterra_protocol::fee_collector::InstantiateMsg as FeeCollectorInstantiate;
 use localterra_protocol::governance::InstantiateMsg as GovernanceInstantiateMsg;
@@ -18,6 +21,8 @@ pub const FEE_COLLECTOR_REPLY_ID: u64 = 1;
 pub const OFFER_REPLY_ID: u64 = 2;
 pub const TRADING_INCENTIVES_REPLY_ID: u64 = 3;
 pub const CW20_TOKEN_REPLY_ID: u64 = 4;
+pub const ARBITRATOR_REGISTRY_REPLY_ID: u64 = 5;
+pub const BTC_RELAY_REPLY_ID: u64 = 6;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -26,14 +31,25 @@ pub fn instantiate(
     _info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, FactoryError> {
+    if msg.btc_confirmations == 0 {
+        return Err(FactoryError::InvalidBtcConfirmations {});
+    }
     let cfg = Config {
         trade_code_id: msg.trade_code_id,
         token_addr: Addr::unchecked(""),
         local_ust_pool_addr: deps.api.addr_validate(&msg.local_ust_pool_addr).unwrap(),
+        terraswap_factory_addr: deps.api.addr_validate(&msg.terraswap_factory_addr)?,
         gov_addr: Addr::unchecked(""),
         offers_addr: Addr::unchecked(""),
         fee_collector_addr: Addr::unchecked(""),
         trading_incentives_addr: Addr::unchecked(""),
+        arbitrator_registry_addr: Addr::unchecked(""),
+        btc_relay_addr: Addr::unchecked(""),
+        dispute_deposit: msg.dispute_deposit,
+        appeal_bond: msg.appeal_bond,
+        btc_pow_limit: msg.btc_pow_limit,
+        btc_confirmations: msg.btc_confirmations,
+        btc_network: msg.btc_network,
     };
     CONFIG.save(deps.storage, &cfg).unwrap();
 
@@ -43,12 +59,28 @@ pub fn instantiate(
     let offer_msg = instantiate_offer_msg(msg.offer_code_id);
     let gov_msg = instantiate_gov_msg(msg.gov_contract_code_id);
     let trading_incentives_msg = instantiate_trading_incentives_msg(msg.trading_incentives_code_id);
+    let arbitrator_registry_msg = instantiate_arbitrator_registry_msg(
+        msg.arbitrator_registry_code_id,
+        msg.arbitrator_min_stake,
+    );
+    let btc_relay_msg = instantiate_btc_relay_msg(
+        msg.btc_relay_code_id,
+        BtcRelayInstantiateMsg {
+            checkpoint: msg.btc_checkpoint,
+            checkpoint_height: msg.btc_checkpoint_height,
+            pow_limit: msg.btc_pow_limit,
+            no_retargeting: msg.btc_network == BtcNetwork::Regtest,
+            min_difficulty_blocks: msg.btc_network == BtcNetwork::Testnet,
+        },
+    );
 
     let r = Response::new()
         .add_submessage(token_msg)
         .add_submessage(offer_msg)
         .add_submessage(fee_collector_msg)
         .add_submessage(trading_incentives_msg)
+        .add_submessage(arbitrator_registry_msg)
+        .add_submessage(btc_relay_msg)
         .add_submessage(gov_msg);
     Ok(r)
 }
@@ -71,6 +103,8 @@ pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, FactoryEr
         OFFER_REPLY_ID => instantiate_offer_reply(deps, msg.result),
         TRADING_INCENTIVES_REPLY_ID => instantiate_trading_incentives_reply(deps, msg.result),
         CW20_TOKEN_REPLY_ID => instantiate_token_reply(deps, msg.result),
+        ARBITRATOR_REGISTRY_REPLY_ID => instantiate_arbitrator_registry_reply(deps, msg.result),
+        BTC_RELAY_REPLY_ID => instantiate_btc_relay_reply(deps, msg.result),
         _ => Err(FactoryError::Std(StdError::generic_err(
             "Unknown reply id.",
         ))),
@@ -227,6 +261,58 @@ fn instantiate_trading_incentives_reply(
     Ok(res)
 }
 
+fn instantiate_arbitrator_registry_msg(code_id: u64, min_stake: Uint128) -> SubMsg {
+    create_instantiate_msg(
+        code_id,
+        to_binary(&ArbitratorRegistryInstantiateMsg { min_stake }).unwrap(),
+        ARBITRATOR_REGISTRY_REPLY_ID,
+        "arbitrator-registry".to_string(),
+    )
+}
+
+fn instantiate_arbitrator_registry_reply(
+    deps: DepsMut,
+    result: ContractResult<SubMsgExecutionResponse>,
+) -> Result<Response, FactoryError> {
+    if result.is_err() {
+        return Err(FactoryError::Std(StdError::generic_err(
+            "Failed to instantiate arbitrator registry contract.",
+        )));
+    }
+
+    let mut cfg = CONFIG.load(deps.storage).unwrap();
+    cfg.arbitrator_registry_addr = get_contract_address_from_reply(deps.as_ref(), result);
+    CONFIG.save(deps.storage, &cfg).unwrap();
+    let res = Response::new().add_attribute("instantiate_contract", "arbitrator_registry");
+    Ok(res)
+}
+
+fn instantiate_btc_relay_msg(code_id: u64, msg: BtcRelayInstantiateMsg) -> SubMsg {
+    create_instantiate_msg(
+        code_id,
+        to_binary(&msg).unwrap(),
+        BTC_RELAY_REPLY_ID,
+        "btc-relay".to_string(),
+    )
+}
+
+fn instantiate_btc_relay_reply(
+    deps: DepsMut,
+    result: ContractResult<SubMsgExecutionResponse>,
+) -> Result<Response, FactoryError> {
+    if result.is_err() {
+        return Err(FactoryError::Std(StdError::generic_err(
+            "Failed to instantiate btc relay contract.",
+        )));
+    }
+
+    let mut cfg = CONFIG.load(deps.storage).unwrap();
+    cfg.btc_relay_addr = get_contract_address_from_reply(deps.as_ref(), result);
+    CONFIG.save(deps.storage, &cfg).unwrap();
+    let res = Response::new().add_attribute("instantiate_contract", "btc_relay");
+    Ok(res)
+}
+
 fn get_contract_address_from_reply(
     deps: Deps,
     result: ContractResult<SubMsgExecutionResponse>,
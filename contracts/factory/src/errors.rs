@@ -8,4 +8,7 @@ pub enum FactoryError {
 
     #[error("Unauthorized")]
     Unauthorized {},
+
+    #[error("Bitcoin payment proofs need at least one confirmation.")]
+    InvalidBtcConfirmations {},
 }
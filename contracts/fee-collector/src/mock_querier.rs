@@ -5,15 +5,18 @@ use serde::{Deserialize, Serialize};
 
 use cosmwasm_std::testing::{MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    from_binary, from_slice, to_binary, Addr, Coin, ContractResult, Decimal, OwnedDeps, Querier,
-    QuerierResult, QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
+    from_binary, from_slice, to_binary, Addr, Binary, Coin, ContractResult, Decimal, OwnedDeps,
+    Querier, QuerierResult, QueryRequest, SystemError, SystemResult, Uint128, WasmQuery,
 };
 use cw20::BalanceResponse;
+use localterra_protocol::bitcoin::BtcNetwork;
 use localterra_protocol::currencies::FiatCurrency;
 use localterra_protocol::factory::Config as FactoryConfig;
 use localterra_protocol::governance::Config as GovConfig;
 use localterra_protocol::offer::{Config as OfferConfig, Offer, OfferState, OfferType, TradeInfo};
-use localterra_protocol::trade::{TradeData as TradeState, TradeState as TradeTradeState};
+use localterra_protocol::trade::{
+    EscrowMode, TradeData as TradeState, TradeState as TradeTradeState,
+};
 use std::collections::HashMap;
 use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper, TerraRoute};
 use terraswap::asset::{AssetInfo, PairInfo};
@@ -239,6 +242,13 @@ impl WasmMockQuerier {
                         max_amount: Uint128::new(500_000_000u128),
                         state: OfferState::Active,
                         timestamp: 1641329895,
+                        expires_at: None,
+                        max_open_trades: None,
+                        taker_cooldown: None,
+                        taker_requirements: None,
+                        escrow_mode: None,
+                        payment_method: None,
+                        btc_payout_address: None,
                     });
                     SystemResult::Ok(ContractResult::from(to_binary(&offer)))
                 }
@@ -250,10 +260,18 @@ impl WasmMockQuerier {
                             trade_code_id: 0,
                             token_addr: Addr::unchecked("local"),
                             local_ust_pool_addr: Addr::unchecked("local-ust"),
+                            terraswap_factory_addr: Addr::unchecked("terraswap-factory"),
                             gov_addr: Addr::unchecked("gov"),
                             offers_addr: Addr::unchecked("offers"),
                             fee_collector_addr: Addr::unchecked("fee-collector"),
                             trading_incentives_addr: Addr::unchecked("trading-incentives"),
+                            arbitrator_registry_addr: Addr::unchecked("arbitrator-registry"),
+                            btc_relay_addr: Addr::unchecked("btc-relay"),
+                            dispute_deposit: Uint128::new(1_000_000),
+                            appeal_bond: Uint128::new(5_000_000),
+                            btc_pow_limit: 0x207f_ffff,
+                            btc_confirmations: 3,
+                            btc_network: BtcNetwork::Regtest,
                         })))
                     } else if contract_addr.contains("gov") {
                         SystemResult::Ok(ContractResult::from(to_binary(&GovConfig {
@@ -277,12 +295,34 @@ impl WasmMockQuerier {
                             buyer: Addr::unchecked("taker"),
                             arbitrator: Addr::unchecked("arbitrator"),
                             taker_contact: "USTKing".to_string(),
+                            maker_contact: None,
                             seller: Addr::unchecked(maker),
                             offer_id: 1,
                             offer_contract: Addr::unchecked("offer"),
                             state: TradeTradeState::Closed,
                             expire_height: 0,
                             ust_amount: Uint128::new(1_000_000u128),
+                            funded_at: None,
+                            disputer: None,
+                            dispute_deposit: Uint128::zero(),
+                            dispute_reason: None,
+                            disputed_at: None,
+                            arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+                            seed_commitment: Binary::from(vec![0u8; 32]),
+                            entropy: None,
+                            dispute_deadline: None,
+                            dismissed_arbitrators: vec![],
+                            ruling: None,
+                            appellant: None,
+                            appeal_bond: Uint128::zero(),
+                            escrow_mode: EscrowMode::Standard,
+                            release_approvals: vec![],
+                            refund_approvals: vec![],
+                            release_nonce: 0,
+                            htlc: None,
+                            payment_hash: None,
+                            btc_payment: None,
+                            payout: None,
                         },
                         offer: Offer {
                             id: 1,
@@ -295,6 +335,13 @@ impl WasmMockQuerier {
                             max_amount: Uint128::new(500_000_000u128),
                             state: OfferState::Active,
                             timestamp: 1641329895,
+                            expires_at: None,
+                            max_open_trades: None,
+                            taker_cooldown: None,
+                            taker_requirements: None,
+                            escrow_mode: None,
+                            payment_method: None,
+                            btc_payout_address: None,
                         },
                         expired: false,
                     })))
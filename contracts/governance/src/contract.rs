@@ -1,19 +1,24 @@
 use crate::errors::GovernanceError;
 use crate::querier::load_token_balance;
 use crate::state::{
-    config_read, config_store, stakers_read, stakers_store, state_read, state_store,
+    config_read, config_store, stakers_read, stakers_store, state_read, state_store, DISPUTES,
+    DISPUTE_VOTERS, VOTE_LOCKS,
 };
 use cosmwasm_std::{
     attr, entry_point, from_binary, to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo,
     Response, StdResult, SubMsg, Uint128, WasmMsg,
 };
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use localterra_protocol::arbitrator_registry::ExecuteMsg as ArbitratorRegistryExecuteMsg;
 use localterra_protocol::factory_util::get_factory_config;
 use localterra_protocol::governance::{
-    Config, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, Staker, State,
+    Config, Cw20HookMsg, Dispute, ExecuteMsg, InstantiateMsg, QueryMsg, Staker, State,
+};
+use localterra_protocol::trade::{
+    ExecuteMsg as TradeExecuteMsg, QueryMsg as TradeQueryMsg, TradeData,
 };
 
-//TODO: Although this contract is named Governance, it doesn't contain any governance function.
+//TODO: Besides voting on escalated disputes, this contract doesn't contain any governance function.
 // Governance features will be added to this contract on a future upgrade.
 #[entry_point]
 pub fn instantiate(
@@ -44,7 +49,156 @@ pub fn execute(
     match msg {
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::Withdraw { shares } => withdraw_tokens(deps, env, info, shares),
+        ExecuteMsg::OpenDispute { trade } => open_dispute(deps, env, info, trade),
+        ExecuteMsg::VoteDispute { trade, release } => vote_dispute(deps, env, info, trade, release),
+        ExecuteMsg::ResolveDispute { trade } => resolve_dispute(deps, env, trade),
+    }
+}
+
+const DISPUTE_VOTING_PERIOD: u64 = 100_800; //Roughly a week.
+
+// Disputes no arbitrator settled in time are handed over by the offer contract.
+fn open_dispute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade: String,
+) -> Result<Response, GovernanceError> {
+    let cfg = config_read(deps.storage).load()?;
+    let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+    if info.sender != factory_cfg.offers_addr {
+        return Err(GovernanceError::Unauthorized {
+            owner: factory_cfg.offers_addr,
+            caller: info.sender,
+        });
+    }
+
+    let trade = deps.api.addr_validate(trade.as_str())?;
+    let dispute = Dispute {
+        trade: trade.clone(),
+        release_votes: Uint128::zero(),
+        refund_votes: Uint128::zero(),
+        end_height: env.block.height + DISPUTE_VOTING_PERIOD,
+        resolved: false,
+    };
+    DISPUTES.save(deps.storage, &trade, &dispute)?;
+
+    let res = Response::new()
+        .add_attribute("action", "open_dispute")
+        .add_attribute("trade", trade)
+        .add_attribute("end_height", dispute.end_height.to_string());
+    Ok(res)
+}
+
+// Votes are weighted by the shares at voting time, which stay locked until the vote ends,
+// so the same tokens can't vote again from another address.
+fn vote_dispute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade: String,
+    release: bool,
+) -> Result<Response, GovernanceError> {
+    let trade = deps.api.addr_validate(trade.as_str())?;
+    let mut dispute = load_dispute(deps.as_ref(), &trade)?;
+    if dispute.resolved | (env.block.height > dispute.end_height) {
+        return Err(GovernanceError::VotingClosed {
+            end_height: dispute.end_height,
+        });
+    }
+    let shares = get_staker_shares(&deps.as_ref(), info.sender.to_string());
+    if shares.is_zero() {
+        return Err(GovernanceError::NoVotingPower {});
+    }
+    if DISPUTE_VOTERS.has(deps.storage, (&trade, &info.sender)) {
+        return Err(GovernanceError::AlreadyVoted { voter: info.sender });
     }
+
+    if release {
+        dispute.release_votes += shares;
+    } else {
+        dispute.refund_votes += shares;
+    }
+    DISPUTES.save(deps.storage, &trade, &dispute)?;
+    DISPUTE_VOTERS.save(deps.storage, (&trade, &info.sender), &release)?;
+    VOTE_LOCKS.update(
+        deps.storage,
+        &info.sender,
+        |locked_until| -> StdResult<u64> {
+            Ok(locked_until.unwrap_or_default().max(dispute.end_height))
+        },
+    )?;
+
+    let res = Response::new()
+        .add_attribute("action", "vote_dispute")
+        .add_attribute("trade", trade)
+        .add_attribute("voter", info.sender)
+        .add_attribute("release", release.to_string())
+        .add_attribute("shares", shares);
+    Ok(res)
+}
+
+// Settles the trade as its arbitrator, a tie refunds the seller.
+fn resolve_dispute(deps: DepsMut, env: Env, trade: String) -> Result<Response, GovernanceError> {
+    let trade = deps.api.addr_validate(trade.as_str())?;
+    let mut dispute = load_dispute(deps.as_ref(), &trade)?;
+    if dispute.resolved {
+        return Err(GovernanceError::VotingClosed {
+            end_height: dispute.end_height,
+        });
+    }
+    if env.block.height <= dispute.end_height {
+        return Err(GovernanceError::VotingInProgress {
+            end_height: dispute.end_height,
+        });
+    }
+
+    let release = dispute.release_votes > dispute.refund_votes;
+    dispute.resolved = true;
+    DISPUTES.save(deps.storage, &trade, &dispute)?;
+
+    let trade_msg = if release {
+        TradeExecuteMsg::Release {}
+    } else {
+        TradeExecuteMsg::Refund {}
+    };
+    let mut res = Response::new().add_message(WasmMsg::Execute {
+        contract_addr: trade.to_string(),
+        msg: to_binary(&trade_msg)?,
+        funds: vec![],
+    });
+
+    // Arbitrators whose ruling is overturned on appeal lose part of their stake
+    let trade_data: TradeData = deps
+        .querier
+        .query_wasm_smart(trade.to_string(), &TradeQueryMsg::State {})?;
+    if let Some(ruling) = trade_data.ruling {
+        if ruling.release != release {
+            let cfg = config_read(deps.storage).load()?;
+            let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+            res = res.add_message(WasmMsg::Execute {
+                contract_addr: factory_cfg.arbitrator_registry_addr.to_string(),
+                msg: to_binary(&ArbitratorRegistryExecuteMsg::Slash {
+                    arbitrator: trade_data.arbitrator.to_string(),
+                })?,
+                funds: vec![],
+            });
+        }
+    }
+
+    let res = res
+        .add_attribute("action", "resolve_dispute")
+        .add_attribute("trade", trade)
+        .add_attribute("release", release.to_string());
+    Ok(res)
+}
+
+fn load_dispute(deps: Deps, trade: &Addr) -> Result<Dispute, GovernanceError> {
+    DISPUTES
+        .may_load(deps.storage, trade)?
+        .ok_or(GovernanceError::DisputeNotFound {
+            trade: trade.clone(),
+        })
 }
 
 fn withdraw_tokens(
@@ -53,6 +207,11 @@ fn withdraw_tokens(
     info: MessageInfo,
     shares: Uint128,
 ) -> Result<Response, GovernanceError> {
+    if let Some(locked_until) = VOTE_LOCKS.may_load(deps.storage, &info.sender)? {
+        if env.block.height <= locked_until {
+            return Err(GovernanceError::SharesLocked { locked_until });
+        }
+    }
     let cfg = config_read(deps.storage).load().unwrap();
     let mut state = state_read(deps.storage).load().unwrap();
     let staker_shares = get_staker_shares(&deps.as_ref(), info.sender.to_string());
@@ -101,6 +260,10 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
         QueryMsg::State {} => to_binary(&query_state(deps)?),
         QueryMsg::Staker { address } => to_binary(&query_staker(deps, address)?),
+        QueryMsg::Dispute { trade } => {
+            let trade = deps.api.addr_validate(trade.as_str())?;
+            to_binary(&DISPUTES.may_load(deps.storage, &trade)?)
+        }
     }
 }
 
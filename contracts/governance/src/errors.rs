@@ -1,8 +1,10 @@
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum GovernanceError {
+    #[error("{0}")]
+    Std(#[from] StdError),
     #[error("Amount is outside of offer amount range.")]
     AmountError {
         amount: Uint128,
@@ -31,4 +33,16 @@ pub enum GovernanceError {
     },
     #[error("Unauthorized.")]
     Unauthorized { owner: Addr, caller: Addr },
+    #[error("Dispute not found.")]
+    DisputeNotFound { trade: Addr },
+    #[error("Voting on this dispute is closed.")]
+    VotingClosed { end_height: u64 },
+    #[error("Voting on this dispute is still open.")]
+    VotingInProgress { end_height: u64 },
+    #[error("Only stakers can vote.")]
+    NoVotingPower {},
+    #[error("Already voted on this dispute.")]
+    AlreadyVoted { voter: Addr },
+    #[error("Shares are locked until the disputes voted on are closed.")]
+    SharesLocked { locked_until: u64 },
 }
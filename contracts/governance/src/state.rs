@@ -1,9 +1,10 @@
-use cosmwasm_std::{Storage, Uint128};
+use cosmwasm_std::{Addr, Storage, Uint128};
 use cosmwasm_storage::{
     bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
     Singleton,
 };
-use localterra_protocol::governance::{Config, State};
+use cw_storage_plus::Map;
+use localterra_protocol::governance::{Config, Dispute, State};
 
 pub static CONFIG_KEY: &[u8] = b"config";
 pub static STATE_KEY: &[u8] = b"state";
@@ -31,3 +32,9 @@ pub fn stakers_read<'a>(storage: &'a dyn Storage) -> ReadonlyBucket<'a, Uint128>
 pub fn stakers_store<'a>(storage: &'a mut dyn Storage) -> Bucket<Uint128> {
     bucket(storage, b"stakers")
 }
+
+pub const DISPUTES: Map<&Addr, Dispute> = Map::new("disputes");
+// Stakers that already voted on a dispute
+pub const DISPUTE_VOTERS: Map<(&Addr, &Addr), bool> = Map::new("dispute_voters");
+// Block height until which a staker's shares back their dispute votes and can't be withdrawn
+pub const VOTE_LOCKS: Map<&Addr, u64> = Map::new("vote_locks");
@@ -2,14 +2,18 @@
 
 use crate::contract::{execute, instantiate, query};
 use crate::errors::GovernanceError;
-use crate::mock_querier::mock_dependencies;
+use crate::mock_querier::{mock_dependencies, mock_trade};
 use cosmwasm_std::testing::mock_info;
-use cosmwasm_std::{from_binary, to_binary, Addr, DepsMut, Empty, Response, Uint128};
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, DepsMut, Empty, Response, SubMsg, Uint128, WasmMsg,
+};
 use cosmwasm_vm::testing::mock_env;
 use cw20::Cw20ReceiveMsg;
+use localterra_protocol::arbitrator_registry::ExecuteMsg as ArbitratorRegistryExecuteMsg;
 use localterra_protocol::governance::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, Staker, State,
+    Cw20HookMsg, Dispute, ExecuteMsg, InstantiateMsg, QueryMsg, Staker, State,
 };
+use localterra_protocol::trade::{ExecuteMsg as TradeExecuteMsg, Ruling, TradeData, TradeState};
 use std::ops::Add;
 
 fn cw20_send(
@@ -209,3 +213,190 @@ fn test_deposit_rewards() {
         staker_a_info.shares.add(staker_b_info.shares)
     );
 }
+
+#[test]
+fn test_dispute_vote() {
+    let staker_balance = Uint128::new(1000u128);
+    let mut deps = mock_dependencies(&[], None);
+    let token_addr = Addr::unchecked("local");
+    let trade = "trade".to_string();
+
+    let env = mock_env();
+    instantiate(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("factory", &[]),
+        InstantiateMsg {},
+    )
+    .unwrap();
+    deps.querier.with_token_balances(&[(
+        &token_addr.to_string(),
+        &[(&env.contract.address.to_string(), &staker_balance)],
+    )]);
+    cw20_send(
+        deps.as_mut(),
+        token_addr.as_str(),
+        "staker".to_string(),
+        staker_balance,
+        Cw20HookMsg::StakeTokens {},
+    )
+    .unwrap();
+
+    //Only the offer contract can open disputes
+    let open_dispute = ExecuteMsg::OpenDispute {
+        trade: trade.clone(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("trade", &[]),
+        open_dispute.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        GovernanceError::Unauthorized { .. }
+    ));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offers", &[]),
+        open_dispute,
+    )
+    .unwrap();
+
+    let vote = |release: bool| ExecuteMsg::VoteDispute {
+        trade: trade.clone(),
+        release,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("any", &[]), vote(true));
+    assert!(matches!(
+        res.err().unwrap(),
+        GovernanceError::NoVotingPower {}
+    ));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("staker", &[]),
+        vote(true),
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("staker", &[]),
+        vote(false),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        GovernanceError::AlreadyVoted { .. }
+    ));
+
+    //Shares that voted can't be withdrawn to vote again from another address
+    let withdraw = ExecuteMsg::Withdraw {
+        shares: Uint128::new(1),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("staker", &[]),
+        withdraw.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        GovernanceError::SharesLocked { .. }
+    ));
+
+    let resolve = ExecuteMsg::ResolveDispute {
+        trade: trade.clone(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        resolve.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        GovernanceError::VotingInProgress { .. }
+    ));
+
+    let dispute: Option<Dispute> = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Dispute {
+                trade: trade.clone(),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let dispute = dispute.unwrap();
+    assert_eq!(dispute.release_votes, staker_balance);
+
+    //The majority decides how the trade is settled
+    let mut env = mock_env();
+    env.block.height = dispute.end_height + 1;
+    let res = execute(deps.as_mut(), env, mock_info("any", &[]), resolve).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(WasmMsg::Execute {
+            contract_addr: trade,
+            msg: to_binary(&TradeExecuteMsg::Release {}).unwrap(),
+            funds: vec![],
+        })]
+    );
+
+    //Overturning an appealed ruling slashes the arbitrator
+    let appealed = "appealed-trade".to_string();
+    deps.querier.with_trade(TradeData {
+        state: TradeState::Appealed,
+        ruling: Some(Ruling {
+            release: false,
+            appeal_deadline: 0,
+        }),
+        ..mock_trade("offer-owner")
+    });
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offers", &[]),
+        ExecuteMsg::OpenDispute {
+            trade: appealed.clone(),
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("staker", &[]),
+        ExecuteMsg::VoteDispute {
+            trade: appealed.clone(),
+            release: true,
+        },
+    )
+    .unwrap();
+    let mut env = mock_env();
+    env.block.height += 100_801;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("any", &[]),
+        ExecuteMsg::ResolveDispute { trade: appealed },
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(WasmMsg::Execute {
+            contract_addr: "arbitrator-registry".to_string(),
+            msg: to_binary(&ArbitratorRegistryExecuteMsg::Slash {
+                arbitrator: "arbitrator".to_string(),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    //The shares are free again once voting is over
+    execute(deps.as_mut(), env, mock_info("staker", &[]), withdraw).unwrap();
+}
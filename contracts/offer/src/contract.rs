@@ -1,21 +1,31 @@
 use cosmwasm_std::{
-    entry_point, to_binary, Addr, Binary, ContractResult, CosmosMsg, Deps, DepsMut, Env,
-    MessageInfo, Order, QueryRequest, Reply, ReplyOn, Response, StdResult, Storage, SubMsg,
-    SubMsgExecutionResponse, WasmMsg, WasmQuery,
+    entry_point, to_binary, Addr, Binary, Coin, ContractResult, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, Order, QueryRequest, Reply, ReplyOn, Response, StdError, StdResult, Storage,
+    SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg, WasmQuery,
 };
 use cw_storage_plus::Bound;
 
+use localterra_protocol::arbitrator_registry::QueryMsg as ArbitratorRegistryQueryMsg;
+use localterra_protocol::bitcoin::Sats;
+use localterra_protocol::currencies::FiatCurrency;
+use localterra_protocol::factory::Config as FactoryConfig;
 use localterra_protocol::factory_util::get_factory_config;
-use localterra_protocol::guards::{assert_min_g_max, assert_ownership};
+use localterra_protocol::governance::ExecuteMsg as GovernanceExecuteMsg;
+use localterra_protocol::guards::{assert_expiration, assert_min_g_max, assert_ownership};
 use localterra_protocol::offer::{
-    offers, Config, ExecuteMsg, InstantiateMsg, Offer, OfferModel, OfferMsg, OfferState, QueryMsg,
-    State, TradeAddr, TradeInfo, TradesIndex,
+    offers, ArbitratorStats, BatchMsg, Config, ExecuteMsg, InstantiateMsg, Offer, OfferModel,
+    OfferMsg, OfferState, OfferType, OfferWithStats, PaymentMethod, Profile, QueryMsg, QueueOrder,
+    Rating, State, TakerRequirements, TradeAddr, TradeInfo, TraderStats, TradesIndex, MAKERS_AWAY,
 };
 use localterra_protocol::trade::{
-    InstantiateMsg as TradeInstantiateMsg, QueryMsg as TradeQueryMsg, TradeData, TradeState,
+    EscrowMode, InstantiateMsg as TradeInstantiateMsg, PayoutMsg, QueryMsg as TradeQueryMsg,
+    TradeData, TradeState,
 };
 
-use crate::state::{config_read, config_storage, state_read, state_storage, trades};
+use crate::state::{
+    config_read, config_storage, state_read, state_storage, trades, ARBITRATOR_STATS, BTC_TXIDS,
+    LAST_TRADES, OPEN_TRADES, PROFILES, RATINGS, TRADER_STATS,
+};
 use localterra_protocol::errors::OfferError;
 
 #[entry_point]
@@ -44,12 +54,20 @@ pub fn execute(
         ExecuteMsg::Activate { id } => activate_offer(deps, env, info, id),
         ExecuteMsg::Pause { id } => pause_offer(deps, env, info, id),
         ExecuteMsg::Update { id, offer } => update_offer(deps, env, info, id, offer),
+        ExecuteMsg::Archive { id } => archive_offer(deps, env, info, id),
+        ExecuteMsg::Delete { id } => delete_offer(deps, env, info, id),
+        ExecuteMsg::SetAway { until } => set_away(deps, env, info, until),
+        ExecuteMsg::Batch { msgs } => batch(deps, env, info, msgs),
         ExecuteMsg::NewTrade {
             offer_id,
             ust_amount,
             taker,
             taker_contact,
-            arbitrator,
+            seed_commitment,
+            hashlock,
+            payment_hash,
+            btc_amount,
+            payout,
         } => create_trade(
             deps,
             env,
@@ -58,8 +76,23 @@ pub fn execute(
             ust_amount,
             taker,
             taker_contact,
-            arbitrator,
+            seed_commitment,
+            hashlock,
+            payment_hash,
+            btc_amount,
+            payout,
         ),
+        ExecuteMsg::UpdateTradeState {} => update_trade_state(deps, env, info),
+        ExecuteMsg::UpdateProfile {
+            encryption_key,
+            metadata,
+            signing_key,
+        } => update_profile(deps, info, encryption_key, metadata, signing_key),
+        ExecuteMsg::RateTrade {
+            trade,
+            rating,
+            comment_hash,
+        } => rate_trade(deps, env, info, trade, rating, comment_hash),
     }
 }
 
@@ -68,20 +101,22 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
         QueryMsg::State {} => to_binary(&query_state(deps)?),
-        QueryMsg::Offers { fiat_currency } => {
-            to_binary(&OfferModel::query_all_offers(deps.storage, fiat_currency)?)
-        }
+        QueryMsg::Offers { fiat_currency } => to_binary(&OfferModel::query_all_offers(
+            deps.storage,
+            env,
+            fiat_currency,
+        )?),
         QueryMsg::OffersQuery {
             owner,
             last_value,
             limit,
-        } => to_binary(&OfferModel::query(deps, owner, last_value, limit)?),
+        } => to_binary(&OfferModel::query(deps, env, owner, last_value, limit)?),
         QueryMsg::OffersByType {
             offer_type,
             last_value,
             limit,
         } => to_binary(&OfferModel::query_by_type(
-            deps, offer_type, last_value, limit,
+            deps, env, offer_type, last_value, limit,
         )?),
         QueryMsg::OffersByFiat {
             fiat_currency,
@@ -89,6 +124,7 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             limit,
         } => to_binary(&OfferModel::query_by_fiat(
             deps,
+            env,
             fiat_currency,
             last_value,
             limit,
@@ -100,12 +136,47 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             limit,
         } => to_binary(&OfferModel::query_by_type_fiat(
             deps,
+            env,
+            offer_type,
+            fiat_currency,
+            last_value,
+            limit,
+        )?),
+        QueryMsg::OffersQueryWithStats {
+            owner,
+            last_value,
+            limit,
+        } => to_binary(&with_maker_stats(
+            deps,
+            OfferModel::query(deps, env, owner, last_value, limit)?,
+        )?),
+        QueryMsg::OffersWithStats {
+            offer_type,
+            fiat_currency,
+            last_value,
+            limit,
+        } => to_binary(&query_offers_with_stats(
+            deps,
+            env,
             offer_type,
             fiat_currency,
             last_value,
             limit,
         )?),
         QueryMsg::Offer { id } => to_binary(&load_offer_by_id(deps.storage, id)?),
+        QueryMsg::TraderStats { address } => to_binary(&query_trader_stats(deps, address)?),
+        QueryMsg::Profile { address } => to_binary(&PROFILES.may_load(deps.storage, &address)?),
+        QueryMsg::ArbitratorStats { arbitrator } => to_binary(
+            &ARBITRATOR_STATS
+                .may_load(deps.storage, &arbitrator)?
+                .unwrap_or_default(),
+        ),
+        QueryMsg::Rating { trade, rater } => {
+            to_binary(&RATINGS.may_load(deps.storage, (&trade, &rater))?)
+        }
+        QueryMsg::ArbitratorQueue { arbitrator, order } => {
+            to_binary(&query_arbitrator_queue(deps, arbitrator, order)?)
+        }
         QueryMsg::TradesQuery {
             user,
             state,
@@ -166,10 +237,14 @@ fn trade_instance_reply(
             trade.addr.as_str(),
             &TradeAddr {
                 trade: trade_addr.clone(),
+                offer_id: trade.offer_id,
                 seller: trade.seller.clone(),
                 buyer: trade.buyer.clone(),
                 arbitrator: trade.arbitrator.clone(),
                 state: trade.state.clone(),
+                disputed_at: None,
+                dispute_reason: None,
+                dispute_deadline: None,
             },
         )
         .unwrap();
@@ -193,6 +268,11 @@ pub fn create_offer(
     msg: OfferMsg,
 ) -> Result<Response, OfferError> {
     assert_min_g_max(msg.min_amount, msg.max_amount)?;
+    assert_expiration(msg.expires_at, env.block.time.seconds())?;
+    validate_taker_requirements(deps.as_ref(), &msg.taker_requirements)?;
+    assert_escrow_mode(&msg)?;
+    assert_payment_method(deps.as_ref(), &msg)?;
+    assert_encryption_key(deps.storage, &info.sender)?;
 
     let mut state = state_storage(deps.storage).load()?;
 
@@ -212,6 +292,13 @@ pub fn create_offer(
             max_amount: msg.max_amount,
             state: OfferState::Active,
             timestamp: env.block.time.seconds(),
+            expires_at: msg.expires_at,
+            max_open_trades: msg.max_open_trades,
+            taker_cooldown: msg.taker_cooldown,
+            taker_requirements: msg.taker_requirements,
+            escrow_mode: msg.escrow_mode,
+            payment_method: msg.payment_method,
+            btc_payout_address: msg.btc_payout_address,
         },
     )
     .offer;
@@ -229,6 +316,70 @@ pub fn create_offer(
     Ok(res)
 }
 
+// The buyer picks the HTLC hashlock when opening the trade, so they have to be the taker.
+fn assert_escrow_mode(msg: &OfferMsg) -> Result<(), OfferError> {
+    if (msg.escrow_mode == Some(EscrowMode::Htlc)) & (msg.offer_type != OfferType::Sell) {
+        return Err(OfferError::InvalidEscrowMode {
+            offer_type: msg.offer_type.clone(),
+            escrow_mode: EscrowMode::Htlc,
+        });
+    }
+    Ok(())
+}
+
+// The seller creates the Lightning invoice and gives its payment hash when opening the trade.
+// Bitcoin payments go to the maker's payout address, so the maker has to be the seller.
+// Both release the escrow on their own, so they can't be combined with other escrow modes.
+fn assert_payment_method(deps: Deps, msg: &OfferMsg) -> Result<(), OfferError> {
+    let valid = match &msg.payment_method {
+        Some(PaymentMethod::Lightning) => msg.offer_type == OfferType::Buy,
+        Some(PaymentMethod::Bitcoin) => match &msg.btc_payout_address {
+            Some(address) => {
+                let cfg = config_read(deps.storage).load()?;
+                let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+                address.require_network(factory_cfg.btc_network)?;
+                msg.offer_type == OfferType::Sell
+            }
+            None => false,
+        },
+        _ => return Ok(()),
+    };
+    let standard_escrow = msg
+        .escrow_mode
+        .as_ref()
+        .map_or(true, |escrow_mode| escrow_mode == &EscrowMode::Standard);
+    if !valid | !standard_escrow {
+        return Err(OfferError::InvalidPaymentMethod {
+            offer_type: msg.offer_type.clone(),
+            payment_method: msg.payment_method.clone().unwrap(),
+        });
+    }
+    Ok(())
+}
+
+// Contacts are only accepted from users that can receive encrypted contacts back
+fn assert_encryption_key(storage: &dyn Storage, addr: &Addr) -> Result<(), OfferError> {
+    if PROFILES.has(storage, addr) {
+        Ok(())
+    } else {
+        Err(OfferError::EncryptionKeyNotFound { addr: addr.clone() })
+    }
+}
+
+fn validate_taker_requirements(
+    deps: Deps,
+    requirements: &Option<TakerRequirements>,
+) -> StdResult<()> {
+    if let Some(requirements) = requirements {
+        let allowlist = requirements.allowlist.iter().flatten();
+        let blocklist = requirements.blocklist.iter().flatten();
+        for addr in allowlist.chain(blocklist) {
+            deps.api.addr_validate(addr.as_str())?;
+        }
+    }
+    Ok(())
+}
+
 pub fn activate_offer(
     deps: DepsMut,
     _env: Env,
@@ -271,12 +422,17 @@ pub fn pause_offer(
 
 pub fn update_offer(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     id: u64,
     msg: OfferMsg,
 ) -> Result<Response, OfferError> {
     assert_min_g_max(msg.min_amount, msg.max_amount)?;
+    assert_expiration(msg.expires_at, env.block.time.seconds())?;
+    validate_taker_requirements(deps.as_ref(), &msg.taker_requirements)?;
+    assert_escrow_mode(&msg)?;
+    assert_payment_method(deps.as_ref(), &msg)?;
+    assert_encryption_key(deps.storage, &info.sender)?;
 
     let mut offer_model = OfferModel::may_load(deps.storage, &id);
 
@@ -285,29 +441,217 @@ pub fn update_offer(
     let offer = offer_model.update(msg);
 
     let res = Response::new()
-        .add_attribute("action", "pause_offer")
+        .add_attribute("action", "update_offer")
+        .add_attribute("id", offer.id.to_string())
+        .add_attribute("owner", offer.owner.to_string());
+
+    Ok(res)
+}
+
+pub fn archive_offer(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, OfferError> {
+    let offer = load_offer_by_id(deps.storage, id)?;
+
+    assert_ownership(info.sender, offer.owner)?;
+
+    let open_trades = OPEN_TRADES
+        .may_load(deps.storage, &id.to_string())?
+        .unwrap_or_default();
+    if open_trades > 0 {
+        return Err(OfferError::OpenTrades {
+            offer_id: id,
+            open_trades: open_trades as u64,
+        });
+    }
+
+    let mut offer_model = OfferModel::may_load(deps.storage, &id);
+    let offer = offer_model.archive()?;
+
+    let res = Response::new()
+        .add_attribute("action", "archive_offer")
         .add_attribute("id", offer.id.to_string())
         .add_attribute("owner", offer.owner.to_string());
 
     Ok(res)
 }
 
+pub fn delete_offer(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, OfferError> {
+    let offer = load_offer_by_id(deps.storage, id)?;
+
+    assert_ownership(info.sender, offer.owner)?;
+
+    // Offers with trade history are kept (archived) so that their trades can still load them.
+    let has_trades = trades()
+        .idx
+        .offer
+        .prefix(id.to_string())
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_some();
+    if has_trades {
+        return Err(OfferError::HasTrades { offer_id: id });
+    }
+
+    let offer = OfferModel::may_load(deps.storage, &id).delete()?;
+
+    let res = Response::new()
+        .add_attribute("action", "delete_offer")
+        .add_attribute("id", offer.id.to_string())
+        .add_attribute("owner", offer.owner.to_string());
+
+    Ok(res)
+}
+
+// Runs every operation as if it was sent on its own, a single failure fails
+// the whole transaction and thus rolls back the operations that succeeded before it.
+pub fn batch(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msgs: Vec<BatchMsg>,
+) -> Result<Response, OfferError> {
+    let mut res = Response::new();
+
+    for msg in msgs {
+        let op_res = match msg {
+            BatchMsg::Pause { id } => pause_offer(deps.branch(), env.clone(), info.clone(), id),
+            BatchMsg::Activate { id } => {
+                activate_offer(deps.branch(), env.clone(), info.clone(), id)
+            }
+            BatchMsg::Update { id, offer } => {
+                update_offer(deps.branch(), env.clone(), info.clone(), id, offer)
+            }
+            BatchMsg::Archive { id } => archive_offer(deps.branch(), env.clone(), info.clone(), id),
+        }?;
+        // Each operation emits the same attributes and messages it does on its own
+        res = res
+            .add_attributes(op_res.attributes)
+            .add_submessages(op_res.messages)
+            .add_events(op_res.events);
+    }
+
+    Ok(res)
+}
+
+pub fn set_away(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    until: u64,
+) -> Result<Response, OfferError> {
+    // Setting `until` to a past timestamp brings the maker back.
+    if until > env.block.time.seconds() {
+        MAKERS_AWAY.save(deps.storage, &info.sender, &until)?;
+    } else {
+        MAKERS_AWAY.remove(deps.storage, &info.sender);
+    }
+
+    let res = Response::new()
+        .add_attribute("action", "set_away")
+        .add_attribute("until", until.to_string())
+        .add_attribute("owner", info.sender);
+
+    Ok(res)
+}
+
 fn create_trade(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     offer_id: u64,
-    ust_amount: String,
+    ust_amount: Uint128,
     taker: String,
     taker_contact: String,
-    arbitrator: String,
+    seed_commitment: Binary,
+    hashlock: Option<Binary>,
+    payment_hash: Option<Binary>,
+    btc_amount: Option<Sats>,
+    payout: Option<PayoutMsg>,
 ) -> Result<Response, OfferError> {
     let cfg = config_read(deps.storage).load().unwrap();
-    // let offer = load_offer_by_id(deps.storage, offer_id).unwrap();
-    let offer = OfferModel::from_store(deps.storage, &offer_id);
-    //     .ok_or(OfferError::InvalidReply {})?; // TODO choose better error
+    let offer = offers()
+        .may_load(deps.storage, &offer_id.to_string())?
+        .ok_or(OfferError::OfferNotFound { offer_id })?;
+
+    if offer.state != OfferState::Active {
+        return Err(OfferError::OfferNotActive {
+            offer_id,
+            state: offer.state,
+        });
+    }
+
+    // Expired offers and offers of makers that are away are treated as paused
+    let offer_state = OfferModel::effective_state(deps.storage, &offer, env.block.time.seconds());
+    if offer_state != OfferState::Active {
+        return Err(OfferError::OfferUnavailable { offer_id });
+    }
+
+    let taker_addr = deps.api.addr_validate(taker.as_str())?;
+    // Limits and cooldowns are per taker, nobody can open trades in someone else's name
+    if taker_addr != info.sender {
+        return Err(OfferError::Unauthorized {
+            owner: taker_addr,
+            caller: info.sender,
+        });
+    }
+    if taker_addr == offer.owner {
+        return Err(OfferError::TakerIsMaker { taker: taker_addr });
+    }
+    assert_encryption_key(deps.storage, &taker_addr)?;
+
+    if (ust_amount < offer.min_amount) | (ust_amount > offer.max_amount) {
+        return Err(OfferError::AmountError {
+            amount: ust_amount,
+            min_amount: offer.min_amount,
+            max_amount: offer.max_amount,
+        });
+    }
+
+    if let Some(requirements) = &offer.taker_requirements {
+        assert_taker_requirements(deps.as_ref(), offer_id, requirements, &taker_addr)?;
+    }
+
+    let offer_key = offer_id.to_string();
+    let open_trades = OPEN_TRADES
+        .may_load(deps.storage, &offer_key)?
+        .unwrap_or_default();
+    if let Some(max_open_trades) = offer.max_open_trades {
+        if open_trades >= max_open_trades {
+            return Err(OfferError::MaxOpenTrades {
+                offer_id,
+                max_open_trades,
+            });
+        }
+    }
+
+    let now = env.block.time.seconds();
+    if let Some(taker_cooldown) = offer.taker_cooldown {
+        let last_trade = LAST_TRADES.may_load(deps.storage, (&offer_key, &taker_addr))?;
+        if let Some(last_trade) = last_trade {
+            let available_at = last_trade + taker_cooldown;
+            if now < available_at {
+                return Err(OfferError::TakerCooldown {
+                    offer_id,
+                    available_at,
+                });
+            }
+        }
+    }
 
     let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+    let arbitrators = arbitrator_candidates(deps.as_ref(), &factory_cfg, &offer, &taker_addr)?;
+
+    OPEN_TRADES.save(deps.storage, &offer_key, &(open_trades + 1))?;
+    LAST_TRADES.save(deps.storage, (&offer_key, &taker_addr), &now)?;
 
     let instantiate_msg = WasmMsg::Instantiate {
         admin: None,
@@ -317,7 +661,12 @@ fn create_trade(
             ust_amount: ust_amount.clone(),
             taker: taker.clone(),
             taker_contact,
-            arbitrator,
+            arbitrators: arbitrators.iter().map(|a| a.to_string()).collect(),
+            seed_commitment,
+            hashlock,
+            payment_hash,
+            btc_amount,
+            payout,
             offers_addr: env.contract.address.to_string(),
             timestamp: env.block.time.seconds(),
         })
@@ -342,6 +691,362 @@ fn create_trade(
     Ok(res)
 }
 
+// The registry's active arbitrators for the offer currency, never one of the traders.
+// The trade picks one of them once the seller funds the escrow.
+fn arbitrator_candidates(
+    deps: Deps,
+    factory_cfg: &FactoryConfig,
+    offer: &Offer,
+    taker: &Addr,
+) -> Result<Vec<Addr>, OfferError> {
+    let arbitrators: Vec<Addr> = deps.querier.query_wasm_smart(
+        factory_cfg.arbitrator_registry_addr.to_string(),
+        &ArbitratorRegistryQueryMsg::ActiveArbitrators {
+            fiat_currency: offer.fiat_currency.clone(),
+        },
+    )?;
+    let candidates: Vec<Addr> = arbitrators
+        .into_iter()
+        .filter(|arbitrator| (arbitrator != &offer.owner) & (arbitrator != taker))
+        .collect();
+    if candidates.is_empty() {
+        return Err(OfferError::NoArbitratorAvailable {
+            fiat_currency: offer.fiat_currency.clone(),
+        });
+    }
+    Ok(candidates)
+}
+
+fn assert_taker_requirements(
+    deps: Deps,
+    offer_id: u64,
+    requirements: &TakerRequirements,
+    taker: &Addr,
+) -> Result<(), OfferError> {
+    if let Some(allowlist) = &requirements.allowlist {
+        if !allowlist.contains(taker) {
+            return Err(OfferError::TakerNotAllowed {
+                offer_id,
+                taker: taker.clone(),
+            });
+        }
+    }
+    if let Some(blocklist) = &requirements.blocklist {
+        if blocklist.contains(taker) {
+            return Err(OfferError::TakerBlocked {
+                offer_id,
+                taker: taker.clone(),
+            });
+        }
+    }
+
+    let stats = TRADER_STATS
+        .may_load(deps.storage, taker)?
+        .unwrap_or_default();
+    if let Some(min_completed_trades) = requirements.min_completed_trades {
+        if stats.completed_trades < min_completed_trades {
+            return Err(OfferError::NotEnoughCompletedTrades {
+                offer_id,
+                completed_trades: stats.completed_trades,
+                min_completed_trades,
+            });
+        }
+    }
+    if let Some(max_dispute_rate) = requirements.max_dispute_rate {
+        let dispute_rate = stats.dispute_rate();
+        if dispute_rate > max_dispute_rate {
+            return Err(OfferError::DisputeRateTooHigh {
+                offer_id,
+                dispute_rate,
+                max_dispute_rate,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Called by trade contracts whenever their state changes, the new state is read from the trade itself.
+pub fn update_trade_state(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, OfferError> {
+    let mut trade_addr = trades()
+        .may_load(deps.storage, info.sender.as_str())?
+        .ok_or(OfferError::TradeNotFound {
+            trade: info.sender.clone(),
+        })?;
+
+    let trade: TradeData = deps
+        .querier
+        .query_wasm_smart(info.sender.to_string(), &TradeQueryMsg::State {})?;
+
+    // Trades paying the same address could otherwise all be released by one transaction
+    if let Some(txid) = trade.btc_payment.as_ref().and_then(|p| p.txid.as_ref()) {
+        match BTC_TXIDS.may_load(deps.storage, txid)? {
+            Some(released) if released != info.sender => {
+                return Err(OfferError::BtcPaymentReused {
+                    txid: txid.clone(),
+                    trade: released,
+                })
+            }
+            _ => BTC_TXIDS.save(deps.storage, txid, &info.sender)?,
+        }
+    }
+
+    // Expired trades that were never funded free their slot once they are refunded
+    if trade.state.is_terminal() & !trade_addr.state.is_terminal() {
+        OPEN_TRADES.update(
+            deps.storage,
+            &trade.offer_id.to_string(),
+            |open_trades| -> StdResult<u32> {
+                Ok(open_trades.unwrap_or_default().saturating_sub(1))
+            },
+        )?;
+        let maker = offers()
+            .load(deps.storage, &trade.offer_id.to_string())?
+            .owner;
+        let now = env.block.time.seconds();
+        for trader in [&trade.buyer, &trade.seller] {
+            TRADER_STATS.update(deps.storage, trader, |stats| -> StdResult<TraderStats> {
+                let mut stats = stats.unwrap_or_default();
+                record_trade(&mut stats, trader, &trade, &maker, now);
+                Ok(stats)
+            })?;
+        }
+    }
+
+    let settled =
+        (trade.state == TradeState::SettledForMaker) | (trade.state == TradeState::SettledForTaker);
+    if settled & !trade_addr.state.is_terminal() {
+        let resolution_time = env
+            .block
+            .time
+            .seconds()
+            .saturating_sub(trade.disputed_at.unwrap_or_default());
+        ARBITRATOR_STATS.update(
+            deps.storage,
+            &trade.arbitrator,
+            |stats| -> StdResult<ArbitratorStats> {
+                let mut stats = stats.unwrap_or_default();
+                stats.add_resolution(&trade.state, resolution_time);
+                Ok(stats)
+            },
+        )?;
+    }
+
+    // Escalated disputes count against the arbitrator that let the deadline pass
+    let escalated = (trade_addr.arbitrator != trade.arbitrator)
+        & trade.dismissed_arbitrators.contains(&trade_addr.arbitrator);
+    if escalated {
+        ARBITRATOR_STATS.update(
+            deps.storage,
+            &trade_addr.arbitrator,
+            |stats| -> StdResult<ArbitratorStats> {
+                let mut stats = stats.unwrap_or_default();
+                stats.missed_deadlines += 1;
+                Ok(stats)
+            },
+        )?;
+    }
+
+    // Governance votes on disputes escalated to it and on appealed rulings
+    let mut res = Response::new();
+    let appealed =
+        (trade.state == TradeState::Appealed) & (trade_addr.state != TradeState::Appealed);
+    if escalated | appealed {
+        let cfg = config_read(deps.storage).load()?;
+        let factory_cfg = get_factory_config(&deps.querier, cfg.factory_addr.to_string());
+        if appealed | (trade.arbitrator == factory_cfg.gov_addr) {
+            res = res.add_message(WasmMsg::Execute {
+                contract_addr: factory_cfg.gov_addr.to_string(),
+                msg: to_binary(&GovernanceExecuteMsg::OpenDispute {
+                    trade: info.sender.to_string(),
+                })?,
+                funds: vec![],
+            });
+        }
+    }
+
+    trade_addr.state = trade.state.clone();
+    trade_addr.arbitrator = trade.arbitrator.clone();
+    trade_addr.disputed_at = trade.disputed_at;
+    trade_addr.dispute_reason = trade.dispute_reason.clone();
+    trade_addr.dispute_deadline = trade.dispute_deadline;
+    trades().save(deps.storage, info.sender.as_str(), &trade_addr)?;
+
+    let res = res
+        .add_attribute("action", "update_trade_state")
+        .add_attribute("trade", info.sender)
+        .add_attribute("state", trade.state.to_string());
+
+    Ok(res)
+}
+
+fn record_trade(stats: &mut TraderStats, trader: &Addr, trade: &TradeData, maker: &Addr, now: u64) {
+    if trade.state == TradeState::Canceled {
+        stats.canceled_trades += 1;
+        return;
+    }
+
+    stats.completed_trades += 1;
+    stats.add_volume(Coin::new(trade.ust_amount.u128(), "uusd"));
+
+    if trade.disputer.is_some() {
+        stats.disputed_trades += 1;
+        if trade.disputer.as_ref() == Some(trader) {
+            stats.disputes_opened += 1;
+        }
+        // Releasing pays the buyer, settlements are named after the maker or taker side
+        let taker = if &trade.buyer == maker {
+            &trade.seller
+        } else {
+            &trade.buyer
+        };
+        let winner = match trade.state {
+            TradeState::Closed => &trade.buyer,
+            TradeState::SettledForMaker => maker,
+            _ => taker,
+        };
+        if trader == winner {
+            stats.disputes_won += 1;
+        } else {
+            stats.disputes_lost += 1;
+        }
+    }
+
+    if (trade.state == TradeState::Closed) & (trader == &trade.seller) {
+        if let Some(funded_at) = trade.funded_at {
+            stats.add_release_time(now.saturating_sub(funded_at));
+        }
+    }
+}
+
+pub fn update_profile(
+    deps: DepsMut,
+    info: MessageInfo,
+    encryption_key: Binary,
+    metadata: Option<String>,
+    signing_key: Option<Binary>,
+) -> Result<Response, OfferError> {
+    if encryption_key.is_empty() {
+        return Err(OfferError::Std(StdError::generic_err(
+            "Encryption key can't be empty.",
+        )));
+    }
+    // Compressed or uncompressed secp256k1 public key
+    if let Some(signing_key) = &signing_key {
+        if (signing_key.len() != 33) & (signing_key.len() != 65) {
+            return Err(OfferError::Std(StdError::generic_err(
+                "Invalid signing key.",
+            )));
+        }
+    }
+
+    PROFILES.save(
+        deps.storage,
+        &info.sender,
+        &Profile {
+            addr: info.sender.clone(),
+            encryption_key,
+            metadata,
+            signing_key,
+        },
+    )?;
+
+    let res = Response::new()
+        .add_attribute("action", "update_profile")
+        .add_attribute("addr", info.sender);
+
+    Ok(res)
+}
+
+const MAX_COMMENT_HASH_LENGTH: usize = 64;
+
+pub fn rate_trade(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade: String,
+    rating: u8,
+    comment_hash: Option<String>,
+) -> Result<Response, OfferError> {
+    if !(1..=5).contains(&rating) {
+        return Err(OfferError::InvalidRating { rating });
+    }
+    if comment_hash
+        .as_ref()
+        .map_or(false, |hash| hash.len() > MAX_COMMENT_HASH_LENGTH)
+    {
+        return Err(OfferError::CommentHashTooLong {
+            max_length: MAX_COMMENT_HASH_LENGTH,
+        });
+    }
+
+    let trade_addr = deps.api.addr_validate(trade.as_str())?;
+    let trade = trades()
+        .may_load(deps.storage, trade_addr.as_str())?
+        .ok_or(OfferError::TradeNotFound {
+            trade: trade_addr.clone(),
+        })?;
+
+    match trade.state {
+        TradeState::Closed | TradeState::SettledForMaker | TradeState::SettledForTaker => {}
+        _ => {
+            return Err(OfferError::TradeNotRateable {
+                trade: trade_addr,
+                state: trade.state,
+            })
+        }
+    }
+
+    let ratee = if info.sender == trade.buyer {
+        trade.seller
+    } else if info.sender == trade.seller {
+        trade.buyer
+    } else {
+        return Err(OfferError::NotTradeParty {
+            trade: trade_addr,
+            caller: info.sender,
+        });
+    };
+
+    if RATINGS.has(deps.storage, (&trade_addr, &info.sender)) {
+        return Err(OfferError::AlreadyRated {
+            trade: trade_addr,
+            rater: info.sender,
+        });
+    }
+
+    RATINGS.save(
+        deps.storage,
+        (&trade_addr, &info.sender),
+        &Rating {
+            trade: trade_addr.clone(),
+            rater: info.sender.clone(),
+            ratee: ratee.clone(),
+            rating,
+            comment_hash,
+            timestamp: env.block.time.seconds(),
+        },
+    )?;
+    TRADER_STATS.update(deps.storage, &ratee, |stats| -> StdResult<TraderStats> {
+        let mut stats = stats.unwrap_or_default();
+        stats.ratings_count += 1;
+        stats.ratings_total += rating as u64;
+        Ok(stats)
+    })?;
+
+    let res = Response::new()
+        .add_attribute("action", "rate_trade")
+        .add_attribute("trade", trade_addr)
+        .add_attribute("rater", info.sender)
+        .add_attribute("ratee", ratee)
+        .add_attribute("rating", rating.to_string());
+
+    Ok(res)
+}
+
 fn query_config(deps: Deps) -> StdResult<Config> {
     let cfg = config_read(deps.storage).load().unwrap();
     Ok(cfg)
@@ -352,12 +1057,56 @@ fn query_state(deps: Deps) -> StdResult<State> {
     Ok(state)
 }
 
+fn query_trader_stats(deps: Deps, address: Addr) -> StdResult<TraderStats> {
+    let stats = TRADER_STATS.may_load(deps.storage, &address)?;
+    Ok(stats.unwrap_or_default())
+}
+
+fn query_offers_with_stats(
+    deps: Deps,
+    env: Env,
+    offer_type: OfferType,
+    fiat_currency: FiatCurrency,
+    last_value: Option<u64>,
+    limit: u32,
+) -> StdResult<Vec<OfferWithStats>> {
+    let offers =
+        OfferModel::query_by_type_fiat(deps, env, offer_type, fiat_currency, last_value, limit)?;
+    with_maker_stats(deps, offers)
+}
+
+fn with_maker_stats(deps: Deps, offers: Vec<Offer>) -> StdResult<Vec<OfferWithStats>> {
+    offers
+        .into_iter()
+        .map(|offer| {
+            let maker_stats = query_trader_stats(deps, offer.owner.clone())?;
+            Ok(OfferWithStats { offer, maker_stats })
+        })
+        .collect()
+}
+
 pub fn load_offer_by_id(storage: &dyn Storage, id: u64) -> StdResult<Offer> {
-    let offer = offers()
-        .may_load(storage, &id.to_string())
-        .unwrap_or_default()
-        .unwrap();
-    Ok(offer)
+    offers().load(storage, &id.to_string())
+}
+
+// Open disputes of an arbitrator sorted by the time they were opened.
+fn query_arbitrator_queue(
+    deps: Deps,
+    arbitrator: Addr,
+    order: QueueOrder,
+) -> StdResult<Vec<TradeAddr>> {
+    let mut queue = trades()
+        .idx
+        .arbitrator_state
+        .prefix((arbitrator, TradeState::Disputed.to_string()))
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, trade)| trade))
+        .collect::<StdResult<Vec<TradeAddr>>>()?;
+    queue.sort_by_key(|trade| trade.disputed_at);
+    if order == QueueOrder::NewestFirst {
+        queue.reverse();
+    }
+    Ok(queue)
 }
 
 pub fn query_trades(
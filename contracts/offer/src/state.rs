@@ -1,17 +1,32 @@
 use cosmwasm_std::{Addr, Storage};
 use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
-use cw_storage_plus::{Index, IndexList, IndexedMap, MultiIndex};
-use localterra_protocol::offer::{Config, State, TradeAddr};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Map, MultiIndex};
+use localterra_protocol::offer::{
+    ArbitratorStats, Config, Profile, Rating, State, TradeAddr, TraderStats,
+};
 
 pub static CONFIG_KEY: &[u8] = b"config";
 pub static STATE_KEY: &[u8] = b"state";
 
+// Number of trades per offer that haven't reached a terminal state
+pub const OPEN_TRADES: Map<&str, u32> = Map::new("open_trades");
+// Time of the last trade opened by a taker on an offer
+pub const LAST_TRADES: Map<(&str, &Addr), u64> = Map::new("last_trades");
+pub const TRADER_STATS: Map<&Addr, TraderStats> = Map::new("trader_stats");
+// Ratings by trade and rater, each party can rate a trade once
+pub const RATINGS: Map<(&Addr, &Addr), Rating> = Map::new("ratings");
+pub const PROFILES: Map<&Addr, Profile> = Map::new("profiles");
+pub const ARBITRATOR_STATS: Map<&Addr, ArbitratorStats> = Map::new("arbitrator_stats");
+// Trade released by each Bitcoin transaction, by txid
+pub const BTC_TXIDS: Map<&str, Addr> = Map::new("btc_txids");
+
 pub struct TradeIndexes<'a> {
     // pk goes to second tuple element
     pub sender: MultiIndex<'a, (Addr, Vec<u8>), TradeAddr>,
     pub recipient: MultiIndex<'a, (Addr, Vec<u8>), TradeAddr>,
     pub arbitrator: MultiIndex<'a, (Addr, Vec<u8>), TradeAddr>,
     pub arbitrator_state: MultiIndex<'a, (Addr, String, Vec<u8>), TradeAddr>,
+    pub offer: MultiIndex<'a, (String, Vec<u8>), TradeAddr>,
 }
 
 impl<'a> IndexList<TradeAddr> for TradeIndexes<'a> {
@@ -21,6 +36,7 @@ impl<'a> IndexList<TradeAddr> for TradeIndexes<'a> {
             &self.recipient,
             &self.arbitrator,
             &self.arbitrator_state,
+            &self.offer,
         ];
         Box::new(v.into_iter())
     }
@@ -48,6 +64,11 @@ pub fn trades<'a>() -> IndexedMap<'a, &'a str, TradeAddr, TradeIndexes<'a>> {
             "trades",                   // TODO replace with TRADES_KEY
             "trades__arbitrator_state", // TODO replace with TRADES_KEY and concat
         ),
+        offer: MultiIndex::new(
+            |d: &TradeAddr, k: Vec<u8>| (d.offer_id.to_string(), k),
+            "trades",        // TODO replace with TRADES_KEY
+            "trades__offer", // TODO replace with TRADES_KEY and concat
+        ),
     };
     IndexedMap::new("trades", indexes)
 }
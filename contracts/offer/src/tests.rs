@@ -2,19 +2,26 @@
 use crate::contract::{execute, instantiate, load_offer_by_id, query};
 // use crate::errors::OfferError;
 use crate::mock_querier::mock_dependencies;
+use crate::state::{trades, OPEN_TRADES};
 use cosmwasm_std::testing::mock_env;
 use cosmwasm_std::{
-    from_binary, to_binary, Addr, CosmosMsg, DepsMut, Empty, Env, MessageInfo, ReplyOn, Response,
-    SubMsg, Uint128, WasmMsg,
+    attr, from_binary, from_slice, to_binary, to_vec, Addr, Binary, Coin, CosmosMsg, Decimal, Deps,
+    DepsMut, Empty, Env, MessageInfo, ReplyOn, Response, SubMsg, Uint128, WasmMsg,
 };
 use cosmwasm_vm::testing::mock_info;
+use localterra_protocol::bitcoin::{BtcAddress, BtcNetwork, Sats};
 use localterra_protocol::currencies::FiatCurrency;
-use localterra_protocol::errors::OfferError;
+use localterra_protocol::errors::{BitcoinError, OfferError};
+use localterra_protocol::governance::ExecuteMsg as GovernanceExecuteMsg;
 use localterra_protocol::offer::{
-    Config, ExecuteMsg, InstantiateMsg, Offer, OfferModel, OfferMsg, OfferState, OfferType,
-    QueryMsg, State, TradesIndex,
+    ArbitratorStats, BatchMsg, Config, ExecuteMsg, InstantiateMsg, Offer, OfferModel, OfferMsg,
+    OfferState, OfferType, OfferWithStats, PaymentMethod, Profile, QueryMsg, QueueOrder, Rating,
+    State, TakerRequirements, TradeAddr, TraderStats, TradesIndex,
+};
+use localterra_protocol::trade::{
+    BtcPayment, DisputeReason, EscrowMode, InstantiateMsg as TradeInstantiateMsg, Ruling,
+    TradeData, TradeState,
 };
-use localterra_protocol::trade::InstantiateMsg as TradeInstantiateMsg;
 
 fn do_init(deps: DepsMut, env: Env, info: MessageInfo) -> Response<Empty> {
     let init_msg = InstantiateMsg {};
@@ -42,13 +49,103 @@ fn proper_init() {
     assert_eq!(conf, expected);
 }
 
+#[test]
+fn profile_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("maker", &[]);
+    do_init(deps.as_mut(), env.clone(), mock_info("factory", &[]));
+
+    //Makers need an encryption key before publishing contacts
+    let offer_msg = OfferMsg {
+        offer_type: OfferType::Buy,
+        maker_contact: "LunaQueen".to_string(),
+        fiat_currency: FiatCurrency::BRL,
+        min_amount: Uint128::new(1),
+        max_amount: Uint128::new(2),
+        expires_at: None,
+        max_open_trades: None,
+        taker_cooldown: None,
+        taker_requirements: None,
+        escrow_mode: None,
+        payment_method: None,
+        btc_payout_address: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Create {
+            offer: offer_msg.clone(),
+        },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::EncryptionKeyNotFound { .. }
+    ));
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::UpdateProfile {
+            encryption_key: Binary::default(),
+            metadata: None,
+            signing_key: None,
+        },
+    );
+    assert!(res.is_err());
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::UpdateProfile {
+            encryption_key: Binary::from(vec![3u8; 33]),
+            metadata: Some("LunaQueen".to_string()),
+            signing_key: None,
+        },
+    )
+    .unwrap();
+    let profile: Option<Profile> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Profile {
+                address: Addr::unchecked("maker"),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(profile.unwrap().encryption_key, Binary::from(vec![3u8; 33]));
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Create { offer: offer_msg },
+    )
+    .unwrap();
+}
+
+fn register_profile(deps: DepsMut, addr: &str) {
+    let msg = ExecuteMsg::UpdateProfile {
+        encryption_key: Binary::from(vec![2u8; 33]),
+        metadata: None,
+        signing_key: None,
+    };
+    execute(deps, mock_env(), mock_info(addr, &[]), msg).unwrap();
+}
+
 fn create_offer(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     offer_type: OfferType,
     fiat_currency: FiatCurrency,
 ) -> Response<Empty> {
+    register_profile(deps.branch(), info.sender.as_str());
     let msg = ExecuteMsg::Create {
         offer: OfferMsg {
             offer_type,
@@ -56,6 +153,13 @@ fn create_offer(
             fiat_currency,
             min_amount: Uint128::from(1u128),
             max_amount: Uint128::from(2u128),
+            expires_at: None,
+            max_open_trades: None,
+            taker_cooldown: None,
+            taker_requirements: None,
+            escrow_mode: None,
+            payment_method: None,
+            btc_payout_address: None,
         },
     };
 
@@ -112,6 +216,13 @@ fn create_offer_test() {
         max_amount: Uint128::new(2),
         state: OfferState::Active,
         timestamp: 1641329895,
+        expires_at: None,
+        max_open_trades: None,
+        taker_cooldown: None,
+        taker_requirements: None,
+        escrow_mode: None,
+        payment_method: None,
+        btc_payout_address: None,
     };
     let queried_offer: Offer =
         from_binary(&query(deps.as_ref(), env.clone(), query_order_by_id).unwrap()).unwrap();
@@ -140,7 +251,8 @@ fn pause_offer_test() {
     assert_eq!(res.messages.len(), 0);
 
     //Load all offers and get the created offer
-    let offers = OfferModel::query_all_offers(&mut deps.storage, FiatCurrency::BRL).unwrap();
+    let offers =
+        OfferModel::query_all_offers(&mut deps.storage, env.clone(), FiatCurrency::BRL).unwrap();
     let offer = &offers[0];
     assert_eq!(offer.state, OfferState::Active);
 
@@ -194,7 +306,8 @@ fn activate_offer_test() {
     assert_eq!(res.messages.len(), 0);
 
     //Load all offers and get the created offer
-    let offers = OfferModel::query_all_offers(&mut deps.storage, FiatCurrency::BRL).unwrap();
+    let offers =
+        OfferModel::query_all_offers(&mut deps.storage, env.clone(), FiatCurrency::BRL).unwrap();
     let offer = &offers[0];
     assert_eq!(offer.state, OfferState::Active);
 
@@ -268,6 +381,13 @@ fn update_offer_test() {
         fiat_currency: FiatCurrency::COP,
         min_amount: Uint128::from(1000000u128),
         max_amount: Uint128::from(5000000u128),
+        expires_at: None,
+        max_open_trades: None,
+        taker_cooldown: None,
+        taker_requirements: None,
+        escrow_mode: None,
+        payment_method: None,
+        btc_payout_address: None,
     };
     let update_offer_msg = ExecuteMsg::Update {
         id: 1,
@@ -285,81 +405,2057 @@ fn update_offer_test() {
 }
 
 #[test]
-fn instantiate_trade() {
+fn batch_test() {
     let mut deps = mock_dependencies(&[], None);
-    let factory = Addr::unchecked("factory");
     let env = mock_env();
-    let info = mock_info(factory.clone().as_str(), &[]);
-
-    //Create Offer
+    let info = mock_info("factory", &[]);
     do_init(deps.as_mut(), env.clone(), info.clone());
-    let res = create_offer(
+    for _ in 0..3 {
+        create_offer(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            OfferType::Buy,
+            FiatCurrency::BRL,
+        );
+    }
+
+    //Pause two offers and update the third one in a single message
+    let offer_msg = OfferMsg {
+        offer_type: OfferType::Sell,
+        maker_contact: "LunaQueen".to_string(),
+        fiat_currency: FiatCurrency::COP,
+        min_amount: Uint128::from(1000000u128),
+        max_amount: Uint128::from(5000000u128),
+        expires_at: None,
+        max_open_trades: None,
+        taker_cooldown: None,
+        taker_requirements: None,
+        escrow_mode: None,
+        payment_method: None,
+        btc_payout_address: None,
+    };
+    let batch_msg = ExecuteMsg::Batch {
+        msgs: vec![
+            BatchMsg::Pause { id: 1 },
+            BatchMsg::Pause { id: 2 },
+            BatchMsg::Update {
+                id: 3,
+                offer: offer_msg.clone(),
+            },
+        ],
+    };
+
+    //Every operation checks ownership
+    let res = execute(
         deps.as_mut(),
         env.clone(),
-        info.clone(),
-        OfferType::Buy,
-        FiatCurrency::BRL,
+        mock_info("other", &[]),
+        batch_msg.clone(),
     );
-    assert_eq!(res.messages.len(), 0);
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::Unauthorized { .. }
+    ));
 
-    let trade_amount = Uint128::new(1000000u128);
-    //Send Message to Create Trade
-    let new_trade_msg = ExecuteMsg::NewTrade {
-        offer_id: 1,
-        arbitrator: "arbitrator".to_string(),
-        taker_contact: "USTKing".to_string(),
-        ust_amount: trade_amount.clone().to_string(),
-        taker: "taker".to_string(),
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), batch_msg).unwrap();
+    assert_eq!(res.messages.len(), 0);
+    let operation = |action: &str, id: &str| {
+        vec![
+            attr("action", action),
+            attr("id", id),
+            attr("owner", "factory"),
+        ]
     };
-    let res = execute(deps.as_mut(), env.clone(), info.clone(), new_trade_msg);
-    assert!(res.is_ok());
-    println!("Res: {:?}", res);
+    assert_eq!(
+        res.attributes,
+        [
+            operation("pause_offer", "1"),
+            operation("pause_offer", "2"),
+            operation("update_offer", "3"),
+        ]
+        .concat()
+    );
+    assert_eq!(
+        load_offer_by_id(&deps.storage, 1).unwrap().state,
+        OfferState::Paused
+    );
+    assert_eq!(
+        load_offer_by_id(&deps.storage, 2).unwrap().state,
+        OfferState::Paused
+    );
+    assert_eq!(
+        load_offer_by_id(&deps.storage, 3).unwrap().fiat_currency,
+        offer_msg.fiat_currency
+    );
 
-    let msg = to_binary(&TradeInstantiateMsg {
-        offer_id: 1,
-        arbitrator: "arbitrator".to_string(),
-        taker_contact: "USTKing".to_string(),
-        ust_amount: trade_amount.clone().to_string(),
-        taker: "taker".to_string(),
-        offers_addr: "offers".to_string(),
-        timestamp: 1641329895,
-    })
+    //A batched operation emits the same attributes as the standalone message
+    let standalone = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Activate { id: 1 },
+    )
+    .unwrap();
+    let batched = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Batch {
+            msgs: vec![BatchMsg::Activate { id: 2 }],
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        batched.attributes,
+        standalone
+            .attributes
+            .into_iter()
+            .map(|a| if a.key == "id" { attr("id", "2") } else { a })
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(batched.events, standalone.events);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Batch {
+            msgs: vec![BatchMsg::Pause { id: 1 }, BatchMsg::Pause { id: 2 }],
+        },
+    )
     .unwrap();
-    let from_binary_msg: TradeInstantiateMsg = from_binary(&msg).unwrap();
-    println!("From Binary: {:?}", from_binary_msg);
-    let instantiate_msg = WasmMsg::Instantiate {
-        admin: None,
-        code_id: 0,
-        msg,
-        funds: vec![],
-        label: "new-trade".to_string(),
-    };
-    //TODO: fix
-    /*
-    let res = res.unwrap();
-    let sub_message = SubMsg {
-        id: 0,
-        msg: CosmosMsg::Wasm(instantiate_msg),
-        gas_limit: None,
-        reply_on: ReplyOn::Success,
-    };
-    assert_eq!(res.messages[0], sub_message);
-     */
 
-    let _trades: Vec<String> = from_binary(
+    //A failing operation fails the whole batch
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Batch {
+            msgs: vec![BatchMsg::Activate { id: 1 }, BatchMsg::Pause { id: 2 }],
+        },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::InvalidStateChange { .. }
+    ));
+}
+
+fn query_brl_buy_offers(deps: Deps, env: Env) -> Vec<Offer> {
+    from_binary(
         &query(
-            deps.as_ref(),
-            mock_env(),
-            QueryMsg::TradesQuery {
-                user: Addr::unchecked("maker"),
-                state: None,
-                index: TradesIndex::Buyer,
+            deps,
+            env,
+            QueryMsg::OffersByTypeFiat {
+                offer_type: OfferType::Buy,
+                fiat_currency: FiatCurrency::BRL,
                 last_value: None,
                 limit: 10,
             },
         )
         .unwrap(),
     )
+    .unwrap()
+}
+
+#[test]
+fn offer_expiration_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("factory", &[]);
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    register_profile(deps.as_mut(), "factory");
+
+    //Expiration must be in the future
+    let mut offer_msg = OfferMsg {
+        offer_type: OfferType::Buy,
+        maker_contact: "LunaQueen".to_string(),
+        fiat_currency: FiatCurrency::BRL,
+        min_amount: Uint128::from(1u128),
+        max_amount: Uint128::from(2u128),
+        expires_at: Some(env.block.time.seconds()),
+        max_open_trades: None,
+        taker_cooldown: None,
+        taker_requirements: None,
+        escrow_mode: None,
+        payment_method: None,
+        btc_payout_address: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Create {
+            offer: offer_msg.clone(),
+        },
+    );
+    assert!(res.is_err());
+
+    //Create an offer that expires in 100 seconds
+    offer_msg.expires_at = Some(env.block.time.seconds() + 100);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Create { offer: offer_msg },
+    )
     .unwrap();
-    println!("Trades: {:?}", &_trades);
+    assert_eq!(query_brl_buy_offers(deps.as_ref(), env.clone()).len(), 1);
+
+    //After expiration the offer is treated as paused
+    let mut expired_env = env.clone();
+    expired_env.block.time = expired_env.block.time.plus_seconds(100);
+    assert_eq!(
+        query_brl_buy_offers(deps.as_ref(), expired_env.clone()).len(),
+        0
+    );
+    let offers =
+        OfferModel::query_all_offers(&deps.storage, expired_env.clone(), FiatCurrency::BRL)
+            .unwrap();
+    assert_eq!(offers[0].state, OfferState::Paused);
+
+    let new_trade_msg = ExecuteMsg::NewTrade {
+        offer_id: 1,
+        taker_contact: "USTKing".to_string(),
+        ust_amount: Uint128::new(1),
+        taker: "taker".to_string(),
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+    };
+    let res = execute(deps.as_mut(), expired_env, info.clone(), new_trade_msg);
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::OfferUnavailable { .. }
+    ));
+}
+
+#[test]
+fn maker_away_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("factory", &[]);
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    assert_eq!(query_brl_buy_offers(deps.as_ref(), env.clone()).len(), 2);
+
+    //Going away hides every offer of the maker without changing their state
+    let until = env.block.time.seconds() + 1000;
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::SetAway { until },
+    )
+    .unwrap();
+    assert_eq!(query_brl_buy_offers(deps.as_ref(), env.clone()).len(), 0);
+    let offer = load_offer_by_id(&deps.storage, 1).unwrap();
+    assert_eq!(offer.state, OfferState::Active);
+
+    //Offers show up again once the maker is back
+    let mut back_env = env.clone();
+    back_env.block.time = back_env.block.time.plus_seconds(1000);
+    assert_eq!(query_brl_buy_offers(deps.as_ref(), back_env).len(), 2);
+
+    //Or when the maker clears the away period
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::SetAway { until: 0 },
+    )
+    .unwrap();
+    assert_eq!(query_brl_buy_offers(deps.as_ref(), env.clone()).len(), 2);
+}
+
+#[test]
+fn create_trade_validation_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let maker = mock_info("maker", &[]);
+    do_init(deps.as_mut(), env.clone(), mock_info("factory", &[]));
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+
+    let new_trade = |offer_id: u64, amount: u128, taker: &str| ExecuteMsg::NewTrade {
+        offer_id,
+        ust_amount: Uint128::new(amount),
+        taker: taker.to_string(),
+        taker_contact: "USTKing".to_string(),
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+    };
+    let taker = mock_info("taker", &[]);
+
+    //Offer must exist
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        taker.clone(),
+        new_trade(2, 1, "taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::OfferNotFound { offer_id: 2 }
+    ));
+
+    //Maker can't take their own offer
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        new_trade(1, 1, "maker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::TakerIsMaker { .. }
+    ));
+
+    //Taker must be able to receive encrypted contacts
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        taker.clone(),
+        new_trade(1, 1, "taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::EncryptionKeyNotFound { .. }
+    ));
+    register_profile(deps.as_mut(), "taker");
+
+    //Amount must be within the offer limits
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        taker.clone(),
+        new_trade(1, 3, "taker"),
+    );
+    assert!(matches!(res.err().unwrap(), OfferError::AmountError { .. }));
+
+    //Traders can't arbitrate their own trades
+    deps.querier.with_arbitrators(&["taker", "maker"]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        taker.clone(),
+        new_trade(1, 1, "taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::NoArbitratorAvailable {
+            fiat_currency: FiatCurrency::BRL
+        }
+    ));
+    deps.querier.with_arbitrators(&["taker", "arbitrator"]);
+
+    //Valid trades instantiate the trade contract
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        taker.clone(),
+        new_trade(1, 1, "taker"),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+    //Traders are never arbitrator candidates
+    if let CosmosMsg::Wasm(WasmMsg::Instantiate { msg, .. }) = &res.messages[0].msg {
+        let msg: TradeInstantiateMsg = from_binary(msg).unwrap();
+        assert_eq!(msg.arbitrators, vec!["arbitrator".to_string()]);
+    } else {
+        panic!("expected trade instantiation");
+    }
+
+    //Offer must be active
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        ExecuteMsg::Pause { id: 1 },
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        taker.clone(),
+        new_trade(1, 1, "taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::OfferNotActive { .. }
+    ));
+}
+
+#[test]
+fn trade_limits_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let maker = mock_info("maker", &[]);
+    do_init(deps.as_mut(), env.clone(), mock_info("factory", &[]));
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    register_profile(deps.as_mut(), "taker");
+    register_profile(deps.as_mut(), "other");
+
+    //Allow a single open trade and one trade per taker every minute
+    let offer_msg = OfferMsg {
+        offer_type: OfferType::Buy,
+        maker_contact: "LunaQueen".to_string(),
+        fiat_currency: FiatCurrency::BRL,
+        min_amount: Uint128::new(1),
+        max_amount: Uint128::new(2),
+        expires_at: None,
+        max_open_trades: Some(1),
+        taker_cooldown: Some(60),
+        taker_requirements: None,
+        escrow_mode: None,
+        payment_method: None,
+        btc_payout_address: None,
+    };
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        ExecuteMsg::Update {
+            id: 1,
+            offer: offer_msg,
+        },
+    )
+    .unwrap();
+
+    let new_trade = |taker: &str| ExecuteMsg::NewTrade {
+        offer_id: 1,
+        ust_amount: Uint128::new(1),
+        taker: taker.to_string(),
+        taker_contact: "USTKing".to_string(),
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("taker", &[]),
+        new_trade("taker"),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+
+    let trade_data = |state: TradeState, expire_height: u64| TradeData {
+        addr: Addr::unchecked("trade"),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("maker"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height,
+        ust_amount: Uint128::new(1),
+        funded_at: None,
+        disputer: None,
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+        state,
+    };
+
+    //The offer is full while the first trade is open
+    save_trade(deps.as_mut(), 1);
+    deps.querier
+        .with_trade(trade_data(TradeState::Created, env.block.height + 600));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[]),
+        new_trade("other"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::MaxOpenTrades {
+            offer_id: 1,
+            max_open_trades: 1
+        }
+    ));
+
+    //Takers can only open trades for themselves
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[]),
+        new_trade("taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::Unauthorized { .. }
+    ));
+
+    //Only known trade contracts can report state changes
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::TradeNotFound { .. }
+    ));
+
+    //A trade that expired without being funded holds the slot until it is refunded
+    deps.querier
+        .with_trade(trade_data(TradeState::Created, env.block.height));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[]),
+        new_trade("other"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::MaxOpenTrades { .. }
+    ));
+    deps.querier
+        .with_trade(trade_data(TradeState::Canceled, env.block.height));
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[]),
+        new_trade("other"),
+    )
+    .unwrap();
+
+    //Closing the trade frees up the slot
+    save_trade(deps.as_mut(), 1);
+    deps.querier
+        .with_trade(trade_data(TradeState::Closed, env.block.height + 600));
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    let trade = trades().load(&deps.storage, "trade").unwrap();
+    assert_eq!(trade.state, TradeState::Closed);
+
+    //But the same taker has to wait for the cooldown
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("taker", &[]),
+        new_trade("taker"),
+    );
+    let available_at = env.block.time.seconds() + 60;
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::TakerCooldown { available_at: a, .. } if a == available_at
+    ));
+
+    let mut later_env = env.clone();
+    later_env.block.time = later_env.block.time.plus_seconds(60);
+    execute(
+        deps.as_mut(),
+        later_env,
+        mock_info("taker", &[]),
+        new_trade("taker"),
+    )
+    .unwrap();
+}
+
+#[test]
+fn taker_requirements_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let maker = mock_info("maker", &[]);
+    do_init(deps.as_mut(), env.clone(), mock_info("factory", &[]));
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    for taker in ["blocked", "taker", "friend"] {
+        register_profile(deps.as_mut(), taker);
+    }
+
+    let update_requirements = |requirements: TakerRequirements| ExecuteMsg::Update {
+        id: 1,
+        offer: OfferMsg {
+            offer_type: OfferType::Buy,
+            maker_contact: "LunaQueen".to_string(),
+            fiat_currency: FiatCurrency::BRL,
+            min_amount: Uint128::new(1),
+            max_amount: Uint128::new(2),
+            expires_at: None,
+            max_open_trades: None,
+            taker_cooldown: None,
+            taker_requirements: Some(requirements),
+            escrow_mode: None,
+            payment_method: None,
+            btc_payout_address: None,
+        },
+    };
+    let new_trade = |taker: &str| ExecuteMsg::NewTrade {
+        offer_id: 1,
+        ust_amount: Uint128::new(1),
+        taker: taker.to_string(),
+        taker_contact: "USTKing".to_string(),
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+    };
+
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        update_requirements(TakerRequirements {
+            min_completed_trades: Some(1),
+            max_dispute_rate: Some(Decimal::percent(50)),
+            allowlist: None,
+            blocklist: Some(vec![Addr::unchecked("blocked")]),
+        }),
+    )
+    .unwrap();
+
+    //Blocked takers are rejected
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("blocked", &[]),
+        new_trade("blocked"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::TakerBlocked { .. }
+    ));
+
+    //Takers without history are rejected
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("taker", &[]),
+        new_trade("taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::NotEnoughCompletedTrades {
+            completed_trades: 0,
+            min_completed_trades: 1,
+            ..
+        }
+    ));
+
+    //A disputed trade counts against the taker once it is settled
+    save_trade(deps.as_mut(), 1);
+    let trade = |state: TradeState| TradeData {
+        addr: Addr::unchecked("trade"),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        funded_at: None,
+        disputer: Some(Addr::unchecked("factory")),
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+        state,
+    };
+    for state in [TradeState::Disputed, TradeState::SettledForTaker] {
+        deps.querier.with_trade(trade(state));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("trade", &[]),
+            ExecuteMsg::UpdateTradeState {},
+        )
+        .unwrap();
+    }
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("taker", &[]),
+        new_trade("taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::DisputeRateTooHigh { dispute_rate, .. } if dispute_rate == Decimal::one()
+    ));
+
+    //Only allowlisted takers can trade when an allowlist is set
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        update_requirements(TakerRequirements {
+            min_completed_trades: None,
+            max_dispute_rate: None,
+            allowlist: Some(vec![Addr::unchecked("friend")]),
+            blocklist: None,
+        }),
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("taker", &[]),
+        new_trade("taker"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::TakerNotAllowed { .. }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("friend", &[]),
+        new_trade("friend"),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 1);
+}
+
+#[test]
+fn trader_stats_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("factory", &[]);
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+
+    let trade = |state: TradeState, disputer: Option<Addr>| TradeData {
+        addr: Addr::unchecked("trade"),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        state,
+        funded_at: Some(env.block.time.seconds() - 120),
+        disputer,
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+    };
+    let query_stats = |deps: Deps, address: &str| -> TraderStats {
+        from_binary(
+            &query(
+                deps,
+                mock_env(),
+                QueryMsg::TraderStats {
+                    address: Addr::unchecked(address),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+
+    //Release two minutes after funding
+    save_trade(deps.as_mut(), 1);
+    deps.querier.with_trade(trade(TradeState::Closed, None));
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+
+    let seller_stats = query_stats(deps.as_ref(), "factory");
+    assert_eq!(seller_stats.completed_trades, 1);
+    assert_eq!(seller_stats.released_trades, 1);
+    assert_eq!(seller_stats.avg_release_time, 120);
+    assert_eq!(seller_stats.volume, vec![Coin::new(1, "uusd")]);
+    let buyer_stats = query_stats(deps.as_ref(), "taker");
+    assert_eq!(buyer_stats.completed_trades, 1);
+    assert_eq!(buyer_stats.released_trades, 0);
+
+    //Reported terminal states are only counted once
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    assert_eq!(query_stats(deps.as_ref(), "taker").completed_trades, 1);
+
+    //Taker opens a dispute that is settled for the maker
+    save_trade(deps.as_mut(), 1);
+    deps.querier.with_trade(trade(
+        TradeState::SettledForMaker,
+        Some(Addr::unchecked("taker")),
+    ));
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+
+    let buyer_stats = query_stats(deps.as_ref(), "taker");
+    assert_eq!(buyer_stats.completed_trades, 2);
+    assert_eq!(buyer_stats.disputed_trades, 1);
+    assert_eq!(buyer_stats.disputes_opened, 1);
+    assert_eq!(buyer_stats.disputes_lost, 1);
+    assert_eq!(buyer_stats.volume, vec![Coin::new(2, "uusd")]);
+    let seller_stats = query_stats(deps.as_ref(), "factory");
+    assert_eq!(seller_stats.disputes_opened, 0);
+    assert_eq!(seller_stats.disputes_won, 1);
+    assert_eq!(seller_stats.released_trades, 1);
+
+    //Maker stats are returned next to their offers
+    let offers: Vec<OfferWithStats> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OffersWithStats {
+                offer_type: OfferType::Buy,
+                fiat_currency: FiatCurrency::BRL,
+                last_value: None,
+                limit: 10,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(offers.len(), 1);
+    assert_eq!(offers[0].offer.id, 1);
+    assert_eq!(offers[0].maker_stats, seller_stats);
+
+    //A disputed trade released to the maker is won by the maker, even though they are the buyer
+    save_trade(deps.as_mut(), 1);
+    let mut released = trade(TradeState::Closed, Some(Addr::unchecked("taker")));
+    released.buyer = Addr::unchecked("factory");
+    released.seller = Addr::unchecked("taker");
+    deps.querier.with_trade(released);
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    assert_eq!(query_stats(deps.as_ref(), "factory").disputes_won, 2);
+    assert_eq!(query_stats(deps.as_ref(), "taker").disputes_lost, 2);
+}
+
+#[test]
+fn rate_trade_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("factory", &[]);
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    save_trade(deps.as_mut(), 1);
+
+    let rate = |rating: u8| ExecuteMsg::RateTrade {
+        trade: "trade".to_string(),
+        rating,
+        comment_hash: Some("9f86d081884c7d659a2feaa0c55ad015".to_string()),
+    };
+
+    //Open trades can't be rated
+    let res = execute(deps.as_mut(), env.clone(), mock_info("taker", &[]), rate(5));
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::TradeNotRateable { .. }
+    ));
+
+    deps.querier.with_trade(TradeData {
+        addr: Addr::unchecked("trade"),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        state: TradeState::Closed,
+        funded_at: None,
+        disputer: None,
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+    });
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+
+    //Only the buyer and the seller can rate, with a rating from 1 to 5
+    let res = execute(deps.as_mut(), env.clone(), mock_info("other", &[]), rate(5));
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::NotTradeParty { .. }
+    ));
+    let res = execute(deps.as_mut(), env.clone(), mock_info("taker", &[]), rate(6));
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::InvalidRating { rating: 6 }
+    ));
+
+    execute(deps.as_mut(), env.clone(), mock_info("taker", &[]), rate(5)).unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("factory", &[]),
+        rate(4),
+    )
+    .unwrap();
+
+    //Each party rates a trade only once
+    let res = execute(deps.as_mut(), env.clone(), mock_info("taker", &[]), rate(1));
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::AlreadyRated { .. }
+    ));
+
+    let rating: Option<Rating> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::Rating {
+                trade: Addr::unchecked("trade"),
+                rater: Addr::unchecked("taker"),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let rating = rating.unwrap();
+    assert_eq!(rating.ratee, Addr::unchecked("factory"));
+    assert_eq!(rating.rating, 5);
+
+    //The maker score is shown next to their offers
+    let offers: Vec<OfferWithStats> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OffersQueryWithStats {
+                owner: Some(Addr::unchecked("factory")),
+                last_value: None,
+                limit: 10,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(offers[0].maker_stats.ratings_count, 1);
+    assert_eq!(
+        offers[0].maker_stats.rating_score(),
+        Decimal::from_ratio(5u128, 1u128)
+    );
+}
+
+fn save_trade(deps: DepsMut, offer_id: u64) {
+    trades()
+        .save(
+            deps.storage,
+            "trade",
+            &TradeAddr {
+                trade: Addr::unchecked("trade"),
+                offer_id,
+                seller: Addr::unchecked("factory"),
+                buyer: Addr::unchecked("taker"),
+                arbitrator: Addr::unchecked("arbitrator"),
+                state: TradeState::Created,
+                disputed_at: None,
+                dispute_reason: None,
+                dispute_deadline: None,
+            },
+        )
+        .unwrap();
+}
+
+#[test]
+fn archive_offer_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let factory = Addr::unchecked("factory");
+    let env = mock_env();
+    let info = mock_info(factory.clone().as_str(), &[]);
+
+    //Create Offer with an open trade
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    save_trade(deps.as_mut(), 1);
+    OPEN_TRADES.save(&mut deps.storage, "1", &1).unwrap();
+    deps.querier.with_trade(TradeData {
+        addr: Addr::unchecked("trade"),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        funded_at: None,
+        disputer: None,
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+        state: TradeState::EscrowFunded,
+    });
+
+    //Archiving an offer with open trades is an error
+    let archive_msg = ExecuteMsg::Archive { id: 1 };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        archive_msg.clone(),
+    );
+    assert!(matches!(res.err().unwrap(), OfferError::OpenTrades { .. }));
+
+    //Once the trade is refunded, only the owner can archive the offer
+    let mut closed_env = env.clone();
+    closed_env.block.height += 600;
+    deps.querier.with_trade(TradeData {
+        addr: Addr::unchecked("trade"),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        funded_at: None,
+        disputer: None,
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+        state: TradeState::Canceled,
+    });
+    execute(
+        deps.as_mut(),
+        closed_env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        closed_env.clone(),
+        mock_info("other", &[]),
+        archive_msg.clone(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::Unauthorized { .. }
+    ));
+    let res = execute(deps.as_mut(), closed_env, info.clone(), archive_msg).unwrap();
+    assert_eq!(res.messages.len(), 0);
+
+    //Archived offers can't be activated and don't show up in searches
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Activate { id: 1 },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::InvalidStateChange { .. }
+    ));
+    let offers: Vec<Offer> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OffersQuery {
+                owner: Some(factory),
+                last_value: None,
+                limit: 10,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(offers.len(), 0);
+    let offers: Vec<Offer> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OffersByFiat {
+                fiat_currency: FiatCurrency::BRL,
+                last_value: None,
+                limit: 10,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(offers.len(), 0);
+
+    //But they can still be loaded by id
+    let offer = load_offer_by_id(&deps.storage, 1).unwrap();
+    assert_eq!(offer.state, OfferState::Archived);
+}
+
+#[test]
+fn delete_offer_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let factory = Addr::unchecked("factory");
+    let env = mock_env();
+    let info = mock_info(factory.clone().as_str(), &[]);
+
+    //Create two offers, the second one with trade history
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    save_trade(deps.as_mut(), 2);
+
+    //Only the owner can delete an offer
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("other", &[]),
+        ExecuteMsg::Delete { id: 1 },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::Unauthorized { .. }
+    ));
+
+    //Offers with trades can't be deleted
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Delete { id: 2 },
+    );
+    assert!(matches!(res.err().unwrap(), OfferError::HasTrades { .. }));
+
+    //Deleted offers are removed from every index
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Delete { id: 1 },
+    )
+    .unwrap();
+    let offers: Vec<Offer> = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::OffersByTypeFiat {
+                offer_type: OfferType::Buy,
+                fiat_currency: FiatCurrency::BRL,
+                last_value: None,
+                limit: 10,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(offers.len(), 1);
+    assert_eq!(offers[0].id, 2);
+}
+
+#[test]
+fn instantiate_trade() {
+    let mut deps = mock_dependencies(&[], None);
+    let factory = Addr::unchecked("factory");
+    let env = mock_env();
+    let info = mock_info(factory.clone().as_str(), &[]);
+
+    //Create Offer
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    let res = create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+    assert_eq!(res.messages.len(), 0);
+    register_profile(deps.as_mut(), "taker");
+
+    let trade_amount = Uint128::new(2u128);
+    //Send Message to Create Trade
+    let new_trade_msg = ExecuteMsg::NewTrade {
+        offer_id: 1,
+        taker_contact: "USTKing".to_string(),
+        ust_amount: trade_amount.clone(),
+        taker: "taker".to_string(),
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("taker", &[]),
+        new_trade_msg,
+    );
+    assert!(res.is_ok());
+    println!("Res: {:?}", res);
+
+    let msg = to_binary(&TradeInstantiateMsg {
+        offer_id: 1,
+        arbitrators: vec!["arbitrator".to_string()],
+        taker_contact: "USTKing".to_string(),
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+        ust_amount: trade_amount.clone(),
+        taker: "taker".to_string(),
+        offers_addr: "offers".to_string(),
+        timestamp: 1641329895,
+    })
+    .unwrap();
+    let from_binary_msg: TradeInstantiateMsg = from_binary(&msg).unwrap();
+    println!("From Binary: {:?}", from_binary_msg);
+    let instantiate_msg = WasmMsg::Instantiate {
+        admin: None,
+        code_id: 0,
+        msg,
+        funds: vec![],
+        label: "new-trade".to_string(),
+    };
+    //TODO: fix
+    /*
+    let res = res.unwrap();
+    let sub_message = SubMsg {
+        id: 0,
+        msg: CosmosMsg::Wasm(instantiate_msg),
+        gas_limit: None,
+        reply_on: ReplyOn::Success,
+    };
+    assert_eq!(res.messages[0], sub_message);
+     */
+
+    let _trades: Vec<String> = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::TradesQuery {
+                user: Addr::unchecked("maker"),
+                state: None,
+                index: TradesIndex::Buyer,
+                last_value: None,
+                limit: 10,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    println!("Trades: {:?}", &_trades);
+}
+
+#[test]
+fn arbitrator_escalation_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("factory", &[]);
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+
+    let trade = |arbitrator: &str, dismissed_arbitrators: &[&str]| TradeData {
+        addr: Addr::unchecked("trade"),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked(arbitrator),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        state: TradeState::Disputed,
+        funded_at: None,
+        disputer: Some(Addr::unchecked("taker")),
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![
+            Addr::unchecked("arbitrator"),
+            Addr::unchecked("arbitrator2"),
+        ],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: dismissed_arbitrators
+            .iter()
+            .map(|a| Addr::unchecked(*a))
+            .collect(),
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+    };
+    let query_stats = |deps: Deps, arbitrator: &str| -> ArbitratorStats {
+        from_binary(
+            &query(
+                deps,
+                mock_env(),
+                QueryMsg::ArbitratorStats {
+                    arbitrator: Addr::unchecked(arbitrator),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+    save_trade(deps.as_mut(), 1);
+
+    //Reassigned disputes count against the previous arbitrator
+    deps.querier
+        .with_trade(trade("arbitrator2", &["arbitrator"]));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 0);
+    assert_eq!(query_stats(deps.as_ref(), "arbitrator").missed_deadlines, 1);
+    assert_eq!(
+        query_stats(deps.as_ref(), "arbitrator2").missed_deadlines,
+        0
+    );
+
+    //Once every candidate missed the deadline the dispute goes to a governance vote
+    deps.querier
+        .with_trade(trade("gov", &["arbitrator", "arbitrator2"]));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    assert_eq!(
+        query_stats(deps.as_ref(), "arbitrator2").missed_deadlines,
+        1
+    );
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "gov".to_string(),
+            msg: to_binary(&GovernanceExecuteMsg::OpenDispute {
+                trade: "trade".to_string(),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+
+    //Appealed rulings are voted on by governance as well, once
+    save_trade(deps.as_mut(), 1);
+    deps.querier.with_trade(TradeData {
+        state: TradeState::Appealed,
+        ruling: Some(Ruling {
+            release: false,
+            appeal_deadline: env.block.height + 100,
+        }),
+        appellant: Some(Addr::unchecked("taker")),
+        ..trade("arbitrator", &[])
+    });
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "gov".to_string(),
+            msg: to_binary(&GovernanceExecuteMsg::OpenDispute {
+                trade: "trade".to_string(),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    );
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 0);
+}
+
+#[test]
+fn arbitrator_dashboard_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("factory", &[]);
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Buy,
+        FiatCurrency::BRL,
+    );
+
+    let now = env.block.time.seconds();
+    let trade = |addr: &str, state: TradeState, disputed_at: u64| TradeData {
+        addr: Addr::unchecked(addr),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        state,
+        funded_at: None,
+        disputer: Some(Addr::unchecked("taker")),
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: Some(DisputeReason::PaymentNotReleased),
+        disputed_at: Some(disputed_at),
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: Some(env.block.height + 100),
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: None,
+        payout: None,
+    };
+    let update_trade = |deps: DepsMut, trade: &str| {
+        execute(
+            deps,
+            env.clone(),
+            mock_info(trade, &[]),
+            ExecuteMsg::UpdateTradeState {},
+        )
+        .unwrap();
+    };
+
+    //Dispute two trades, the newest first
+    for (addr, disputed_at) in [("trade1", now - 60), ("trade2", now - 600)] {
+        trades()
+            .save(
+                &mut deps.storage,
+                addr,
+                &TradeAddr {
+                    trade: Addr::unchecked(addr),
+                    offer_id: 1,
+                    seller: Addr::unchecked("factory"),
+                    buyer: Addr::unchecked("taker"),
+                    arbitrator: Addr::unchecked("arbitrator"),
+                    state: TradeState::EscrowFunded,
+                    disputed_at: None,
+                    dispute_reason: None,
+                    dispute_deadline: None,
+                },
+            )
+            .unwrap();
+        deps.querier
+            .with_trade(trade(addr, TradeState::Disputed, disputed_at));
+        update_trade(deps.as_mut(), addr);
+    }
+
+    let query_queue = |deps: Deps, order: QueueOrder| -> Vec<TradeAddr> {
+        from_binary(
+            &query(
+                deps,
+                mock_env(),
+                QueryMsg::ArbitratorQueue {
+                    arbitrator: Addr::unchecked("arbitrator"),
+                    order,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+    let queue = query_queue(deps.as_ref(), QueueOrder::OldestFirst);
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue[0].trade, Addr::unchecked("trade2"));
+    assert_eq!(
+        queue[0].dispute_reason,
+        Some(DisputeReason::PaymentNotReleased)
+    );
+    assert_eq!(queue[0].dispute_deadline, Some(env.block.height + 100));
+    let queue = query_queue(deps.as_ref(), QueueOrder::NewestFirst);
+    assert_eq!(queue[0].trade, Addr::unchecked("trade1"));
+
+    //Settled disputes leave the queue and are added to the arbitrator stats
+    deps.querier
+        .with_trade(trade("trade2", TradeState::SettledForMaker, now - 600));
+    update_trade(deps.as_mut(), "trade2");
+
+    let queue = query_queue(deps.as_ref(), QueueOrder::OldestFirst);
+    assert_eq!(queue.len(), 1);
+    let stats: ArbitratorStats = from_binary(
+        &query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ArbitratorStats {
+                arbitrator: Addr::unchecked("arbitrator"),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(stats.resolved_disputes, 1);
+    assert_eq!(stats.avg_resolution_time, 600);
+    assert_eq!(stats.settled_for_maker, 1);
+    assert_eq!(stats.settled_for_taker, 0);
+}
+
+#[test]
+fn htlc_offer_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let maker = mock_info("maker", &[]);
+    do_init(deps.as_mut(), env.clone(), mock_info("factory", &[]));
+    register_profile(deps.as_mut(), "maker");
+
+    let create = |offer_type: OfferType| ExecuteMsg::Create {
+        offer: OfferMsg {
+            offer_type,
+            maker_contact: "LunaQueen".to_string(),
+            fiat_currency: FiatCurrency::BRL,
+            min_amount: Uint128::new(1),
+            max_amount: Uint128::new(2),
+            expires_at: None,
+            max_open_trades: None,
+            taker_cooldown: None,
+            taker_requirements: None,
+            escrow_mode: Some(EscrowMode::Htlc),
+            payment_method: None,
+            btc_payout_address: None,
+        },
+    };
+
+    //The buyer has to be the taker to pick the hashlock
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        create(OfferType::Buy),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::InvalidEscrowMode { .. }
+    ));
+    execute(deps.as_mut(), env, maker, create(OfferType::Sell)).unwrap();
+}
+
+#[test]
+fn lightning_offer_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let maker = mock_info("maker", &[]);
+    do_init(deps.as_mut(), env.clone(), mock_info("factory", &[]));
+    register_profile(deps.as_mut(), "maker");
+
+    let create = |offer_type: OfferType| ExecuteMsg::Create {
+        offer: OfferMsg {
+            offer_type,
+            maker_contact: "LunaQueen".to_string(),
+            fiat_currency: FiatCurrency::BRL,
+            min_amount: Uint128::new(1),
+            max_amount: Uint128::new(2),
+            expires_at: None,
+            max_open_trades: None,
+            taker_cooldown: None,
+            taker_requirements: None,
+            escrow_mode: None,
+            payment_method: Some(PaymentMethod::Lightning),
+            btc_payout_address: None,
+        },
+    };
+
+    //The seller has to be the taker to give the invoice's payment hash
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        create(OfferType::Sell),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::InvalidPaymentMethod { .. }
+    ));
+    execute(deps.as_mut(), env, maker, create(OfferType::Buy)).unwrap();
+}
+
+#[test]
+fn bitcoin_offer_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let maker = mock_info("maker", &[]);
+    do_init(deps.as_mut(), env.clone(), mock_info("factory", &[]));
+    register_profile(deps.as_mut(), "maker");
+
+    let create = |offer_type: OfferType, btc_payout_address: Option<&str>| ExecuteMsg::Create {
+        offer: OfferMsg {
+            offer_type,
+            maker_contact: "LunaQueen".to_string(),
+            fiat_currency: FiatCurrency::BRL,
+            min_amount: Uint128::new(1),
+            max_amount: Uint128::new(2),
+            expires_at: None,
+            max_open_trades: None,
+            taker_cooldown: None,
+            taker_requirements: None,
+            escrow_mode: None,
+            payment_method: Some(PaymentMethod::Bitcoin),
+            btc_payout_address: btc_payout_address.map(|address| address.parse().unwrap()),
+        },
+    };
+    let address = "bcrt1q5sneatj842n5zldxys68jksprn9sajrsrmww5z";
+
+    //The maker sells and has to say where the bitcoin goes
+    for msg in vec![
+        create(OfferType::Buy, Some(address)),
+        create(OfferType::Sell, None),
+    ] {
+        let res = execute(deps.as_mut(), env.clone(), maker.clone(), msg);
+        assert!(matches!(
+            res.err().unwrap(),
+            OfferError::InvalidPaymentMethod { .. }
+        ));
+    }
+
+    //On the network the factory is configured for, regtest shares testnet legacy addresses
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        maker.clone(),
+        create(
+            OfferType::Sell,
+            Some("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+        ),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::Bitcoin(BitcoinError::WrongNetwork {
+            network: BtcNetwork::Mainnet,
+            expected: BtcNetwork::Regtest
+        })
+    ));
+    for address in vec![address, "mvUvaMDisTe1Vn2ZSw2FeJVtbEAEUcy9uF"] {
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            maker.clone(),
+            create(OfferType::Sell, Some(address)),
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn btc_address_test() {
+    let vectors = vec![
+        (
+            "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+            BtcNetwork::Mainnet,
+            "76a91477bff20c60e522dfaa3350c39b030a5d004e839a88ac",
+        ),
+        (
+            "2N8DCGaZDDo2V1csvXbLLrxdm674QqoJG2p",
+            BtcNetwork::Testnet,
+            "a914a4279eae47aaa7417da62434795a011ccb0ec87087",
+        ),
+        (
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+            BtcNetwork::Mainnet,
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+        ),
+        (
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+            BtcNetwork::Testnet,
+            "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+        ),
+        (
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+            BtcNetwork::Mainnet,
+            "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        ),
+    ];
+    for (address, network, script_pubkey) in vectors {
+        let parsed: BtcAddress = address.parse().unwrap();
+        assert_eq!(parsed.network, network);
+        let script_hex: String = parsed
+            .script_pubkey()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(script_hex, script_pubkey);
+        assert_eq!(parsed.to_string(), address);
+        let json = format!("\"{}\"", address);
+        assert_eq!(from_slice::<BtcAddress>(json.as_bytes()).unwrap(), parsed);
+        assert_eq!(to_vec(&parsed).unwrap(), json.as_bytes());
+    }
+
+    //Bech32 addresses can be upper case but are always shown in lower case
+    let parsed: BtcAddress = "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        parsed.to_string(),
+        "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+    );
+
+    for (address, err) in vec![
+        (
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
+            BitcoinError::InvalidChecksum {},
+        ),
+        // Version 0 with a bech32m checksum
+        (
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+            BitcoinError::InvalidChecksum {},
+        ),
+        (
+            "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN3",
+            BitcoinError::InvalidChecksum {},
+        ),
+        (
+            "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3t4",
+            BitcoinError::InvalidAddress {},
+        ),
+        (
+            "0BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+            BitcoinError::InvalidAddress {},
+        ),
+        ("LunaQueen", BitcoinError::InvalidAddress {}),
+    ] {
+        assert_eq!(address.parse::<BtcAddress>().err().unwrap(), err);
+    }
+    assert!(from_slice::<BtcAddress>(b"\"bc1gmk9yu\"").is_err());
+
+    assert!(Sats::new(2_100_000_000_000_000).is_valid_amount());
+    assert!(!Sats::new(2_100_000_000_000_001).is_valid_amount());
+    assert!(!Sats::default().is_valid_amount());
+}
+
+#[test]
+fn btc_payment_reuse_test() {
+    let mut deps = mock_dependencies(&[], None);
+    let env = mock_env();
+    let info = mock_info("factory", &[]);
+    do_init(deps.as_mut(), env.clone(), info.clone());
+    create_offer(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        OfferType::Sell,
+        FiatCurrency::BRL,
+    );
+
+    let txid = "9f1a6c0e4b2d8a7f3c5e1b0d2a4c6e8f0a1b3c5d7e9f1a2b4c6d8e0f1a3b5c7d";
+    let trade = |addr: &str| TradeData {
+        addr: Addr::unchecked(addr),
+        factory_addr: Addr::unchecked("factory"),
+        buyer: Addr::unchecked("taker"),
+        seller: Addr::unchecked("factory"),
+        taker_contact: "USTKing".to_string(),
+        maker_contact: None,
+        arbitrator: Addr::unchecked("arbitrator"),
+        offer_contract: Addr::unchecked("offers"),
+        offer_id: 1,
+        expire_height: env.block.height + 600,
+        ust_amount: Uint128::new(1),
+        state: TradeState::Closed,
+        funded_at: None,
+        disputer: None,
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        arbitrator_candidates: vec![Addr::unchecked("arbitrator")],
+        seed_commitment: Binary::from(vec![0u8; 32]),
+        entropy: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode: EscrowMode::Standard,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc: None,
+        payment_hash: None,
+        btc_payment: Some(BtcPayment {
+            address: "bcrt1q5sneatj842n5zldxys68jksprn9sajrsrmww5z"
+                .parse()
+                .unwrap(),
+            sats: Sats::new(150_000),
+            txid: Some(txid.to_string()),
+        }),
+        payout: None,
+    };
+    for addr in ["trade1", "trade2"] {
+        trades()
+            .save(
+                &mut deps.storage,
+                addr,
+                &TradeAddr {
+                    trade: Addr::unchecked(addr),
+                    offer_id: 1,
+                    seller: Addr::unchecked("factory"),
+                    buyer: Addr::unchecked("taker"),
+                    arbitrator: Addr::unchecked("arbitrator"),
+                    state: TradeState::EscrowFunded,
+                    disputed_at: None,
+                    dispute_reason: None,
+                    dispute_deadline: None,
+                },
+            )
+            .unwrap();
+    }
+
+    //The first trade released by a transaction keeps it
+    deps.querier.with_trade(trade("trade1"));
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade1", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade1", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    )
+    .unwrap();
+
+    //Another trade paying the same address can't be released with it
+    deps.querier.with_trade(trade("trade2"));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("trade2", &[]),
+        ExecuteMsg::UpdateTradeState {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        OfferError::BtcPaymentReused { trade, .. } if trade == Addr::unchecked("trade1")
+    ));
 }
@@ -1,22 +1,33 @@
 use std::ops::{Add, Sub};
-use std::str::FromStr;
 
 use cosmwasm_std::{
     entry_point, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env,
-    MessageInfo, QuerierWrapper, QueryRequest, Response, StdResult, SubMsg, Uint128, WasmMsg,
-    WasmQuery,
+    MessageInfo, QuerierWrapper, QueryRequest, Reply, ReplyOn, Response, StdResult, Storage,
+    SubMsg, Uint128, WasmMsg, WasmQuery,
 };
+use sha2::{Digest, Sha256};
+use terraswap::asset::{Asset, AssetInfo, PairInfo};
+use terraswap::factory::QueryMsg as TerraswapFactoryQueryMsg;
+use terraswap::pair::ExecuteMsg::Swap;
 
+use localterra_protocol::bitcoin::{
+    bits_to_target, merkle_root, to_display_hash, BlockHeader, Sats, Transaction,
+};
+use localterra_protocol::btc_relay::QueryMsg as BtcRelayQueryMsg;
 use localterra_protocol::factory::Config as FactoryConfig;
 use localterra_protocol::factory_util::get_factory_config;
 use localterra_protocol::offer::{
-    Config as OfferConfig, Offer, OfferType, QueryMsg as OfferQueryMsg,
+    Config as OfferConfig, ExecuteMsg as OfferMsg, Offer, OfferType, PaymentMethod, Profile,
+    QueryMsg as OfferQueryMsg,
+};
+use localterra_protocol::trade::{
+    BtcPayment, DisputeReason, EscrowMode, ExecuteMsg, Htlc, InstantiateMsg, Payout, PayoutMsg,
+    PayoutSwap, QueryMsg, Ruling, TradeData, TradeMessage, TradeMessageKind, TradeState,
 };
-use localterra_protocol::trade::{ExecuteMsg, InstantiateMsg, QueryMsg, TradeData, TradeState};
 use localterra_protocol::trading_incentives::ExecuteMsg as TradingIncentivesMsg;
 
 use crate::errors::TradeError;
-use crate::state::{state as state_storage, state_read};
+use crate::state::{messages, messages_read, payout_fallback, state as state_storage, state_read};
 use crate::taxation::{compute_tax, deduct_tax};
 
 #[entry_point]
@@ -49,7 +60,7 @@ pub fn instantiate(
     let expire_height = env.block.height + 600; //Roughly 1h.
 
     //Check that ust_amount is inside Offer limits
-    let amount = Uint128::new(u128::from_str(msg.ust_amount.as_str()).unwrap());
+    let amount = msg.ust_amount;
     if amount > offer.max_amount || amount < offer.min_amount {
         return Err(TradeError::AmountError {
             amount,
@@ -62,7 +73,64 @@ pub fn instantiate(
     let recipient: Addr;
     let sender: Addr;
     let taker = deps.api.addr_validate(msg.taker.as_str()).unwrap();
-    let arbitrator = deps.api.addr_validate(msg.arbitrator.as_str()).unwrap();
+    let arbitrator_candidates = msg
+        .arbitrators
+        .iter()
+        .map(|arbitrator| deps.api.addr_validate(arbitrator))
+        .collect::<StdResult<Vec<Addr>>>()?;
+    if arbitrator_candidates.is_empty() {
+        return Err(TradeError::InstantiationError {
+            message: "No arbitrator candidates.".to_string(),
+        });
+    }
+    if msg.seed_commitment.len() != 32 {
+        return Err(TradeError::InvalidSeedCommitment {});
+    }
+    let escrow_mode = offer.escrow_mode.clone().unwrap_or(EscrowMode::Standard);
+    let htlc = if escrow_mode == EscrowMode::Htlc {
+        match msg.hashlock {
+            Some(hashlock) if hashlock.len() == 32 => Some(Htlc {
+                hashlock,
+                timelock: env.block.height + HTLC_TIMELOCK,
+                preimage: None,
+            }),
+            _ => return Err(TradeError::InvalidHashlock {}),
+        }
+    } else {
+        None
+    };
+    let payment_hash = if offer.payment_method == Some(PaymentMethod::Lightning) {
+        match msg.payment_hash {
+            Some(payment_hash) if payment_hash.len() == 32 => Some(payment_hash),
+            _ => return Err(TradeError::InvalidPaymentHash {}),
+        }
+    } else {
+        None
+    };
+    let btc_payment = if offer.payment_method == Some(PaymentMethod::Bitcoin) {
+        match (offer.btc_payout_address.clone(), msg.btc_amount) {
+            (Some(address), Some(sats)) if sats.is_valid_amount() => Some(BtcPayment {
+                address,
+                sats,
+                txid: None,
+            }),
+            _ => return Err(TradeError::InvalidBtcPayment {}),
+        }
+    } else {
+        None
+    };
+
+    // Only the buyer says where the escrow goes, so the taker has to be the buyer
+    let payout = match msg.payout {
+        Some(_) if offer.offer_type == OfferType::Buy => return Err(TradeError::InvalidPayout {}),
+        Some(payout) => Some(load_payout(
+            deps.as_ref(),
+            &offers_cfg.factory_addr,
+            &taker,
+            payout,
+        )?),
+        None => None,
+    };
 
     if offer.offer_type == OfferType::Buy {
         recipient = offer.owner; // maker
@@ -81,18 +149,49 @@ pub fn instantiate(
         offer_contract: offer_contract.clone(),
         offer_id,
         taker_contact: msg.taker_contact,
-        arbitrator,
+        maker_contact: None,
+        arbitrator: Addr::unchecked(""),
+        arbitrator_candidates,
+        seed_commitment: msg.seed_commitment,
+        entropy: None,
         state: TradeState::Created,
         expire_height,
         ust_amount: amount,
+        funded_at: None,
+        disputer: None,
+        dispute_deposit: Uint128::zero(),
+        dispute_reason: None,
+        disputed_at: None,
+        dispute_deadline: None,
+        dismissed_arbitrators: vec![],
+        ruling: None,
+        appellant: None,
+        appeal_bond: Uint128::zero(),
+        escrow_mode,
+        release_approvals: vec![],
+        refund_approvals: vec![],
+        release_nonce: 0,
+        htlc,
+        payment_hash,
+        btc_payment,
+        payout,
     };
 
     //Set state to EscrowFunded if enough UST was sent in the message.
     if !info.funds.is_empty() {
+        // The funds come from the taker, only the seller can lock them in the escrow
+        if taker != trade.seller {
+            return Err(TradeError::Unauthorized {
+                owner: trade.seller,
+                arbitrator: trade.arbitrator,
+                caller: taker,
+            });
+        }
         //TODO: Check for Luna or other Terra native tokens.
         let ust_amount = get_ust_amount(info.clone());
         if ust_amount >= amount {
-            trade.state = TradeState::EscrowFunded
+            trade.state = TradeState::EscrowFunded;
+            trade.funded_at = Some(env.block.time.seconds());
         }
     }
 
@@ -116,10 +215,44 @@ pub fn execute(
 ) -> Result<Response, TradeError> {
     let state = state_storage(deps.storage).load().unwrap();
     match msg {
-        ExecuteMsg::FundEscrow {} => fund_escrow(deps, env, info, state),
+        ExecuteMsg::FundEscrow {
+            entropy,
+            btc_amount,
+        } => fund_escrow(deps, env, info, state, entropy, btc_amount),
+        ExecuteMsg::ProvideEntropy { entropy } => provide_entropy(deps, info, state, entropy),
         ExecuteMsg::Refund {} => refund(deps, env, info, state),
         ExecuteMsg::Release {} => release(deps, env, info, state),
-        ExecuteMsg::Dispute {} => dispute(deps, env, info, state),
+        ExecuteMsg::ReleaseWithSignature { nonce, signature } => {
+            release_with_signature(deps, env, info, state, nonce, signature)
+        }
+        ExecuteMsg::FiatDeposited {} => fiat_deposited(deps, env, info, state),
+        ExecuteMsg::Dispute { reason } => dispute(deps, env, info, state, reason),
+        ExecuteMsg::AssignArbitrator { seed } => assign_arbitrator(deps, env, state, seed),
+        ExecuteMsg::Escalate {} => escalate(deps, env, state),
+        ExecuteMsg::Appeal {} => appeal(deps, env, info, state),
+        ExecuteMsg::ExecuteRuling {} => execute_ruling(deps, env, state),
+        ExecuteMsg::Claim { preimage } => claim(deps, env, state, preimage),
+        ExecuteMsg::SettleLightning { preimage } => settle_lightning(deps, env, state, preimage),
+        ExecuteMsg::ProveBtcPayment {
+            tx,
+            merkle_branch,
+            tx_index,
+            headers,
+        } => prove_btc_payment(deps, env, state, tx, merkle_branch, tx_index, headers),
+        ExecuteMsg::PostMessage { kind, content } => {
+            post_message(deps, env, info, state, kind, content)
+        }
+        ExecuteMsg::SetMakerContact { maker_contact } => {
+            set_maker_contact(deps, info, state, maker_contact)
+        }
+    }
+}
+
+#[entry_point]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, TradeError> {
+    match msg.id {
+        PAYOUT_REPLY_ID => send_payout_fallback(deps),
+        _ => Err(TradeError::InvalidReply {}),
     }
 }
 
@@ -127,6 +260,7 @@ pub fn execute(
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::State {} => to_binary(&query_state(deps)?),
+        QueryMsg::Messages {} => to_binary(&query_messages(deps)?),
     }
 }
 
@@ -135,6 +269,11 @@ fn query_state(deps: Deps) -> StdResult<TradeData> {
     Ok(state)
 }
 
+fn query_messages(deps: Deps) -> StdResult<Vec<TradeMessage>> {
+    let messages = messages_read(deps.storage).may_load()?;
+    Ok(messages.unwrap_or_default())
+}
+
 fn load_offer(querier: QuerierWrapper, offer_id: u64, offer_contract: String) -> Option<Offer> {
     let load_offer_result: StdResult<Offer> =
         querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
@@ -154,7 +293,16 @@ fn fund_escrow(
     env: Env,
     info: MessageInfo,
     mut trade: TradeData,
+    entropy: Option<Binary>,
+    btc_amount: Option<Sats>,
 ) -> Result<Response, TradeError> {
+    if info.sender != trade.seller {
+        return Err(TradeError::Unauthorized {
+            owner: trade.seller,
+            arbitrator: trade.arbitrator,
+            caller: info.sender,
+        });
+    }
     //Check if trade is expired.
     if env.block.height >= trade.expire_height {
         return Err(TradeError::Expired {
@@ -164,9 +312,18 @@ fn fund_escrow(
     }
     // Check if escrow has already been funded
     // TODO also base this on actual balance, switch to cancelled state and refund automatically on diffs
-    if trade.state == TradeState::EscrowFunded {
+    if trade.state != TradeState::Created {
         return Err(TradeError::AlreadyFundedError {});
     }
+    // The buyer picked the sats amount, the seller has to agree to it before locking the escrow
+    if let Some(btc_payment) = &trade.btc_payment {
+        if btc_amount != Some(btc_payment.sats) {
+            return Err(TradeError::BtcAmountMismatch {
+                sats: btc_payment.sats,
+                confirmed: btc_amount,
+            });
+        }
+    }
     //TODO: Convert to UST if trade is for any other stablecoin or Luna,
     // skip conversion entirely if fee was paid in $LOCAL.
     let ust_amount = if !info.funds.is_empty() {
@@ -190,6 +347,9 @@ fn fund_escrow(
         trade.offer_contract.to_string(),
     )
     .unwrap(); //at this stage, offer is guaranteed to exists.
+    if let Some(entropy) = entropy {
+        accept_entropy(&mut trade, &offer, &info.sender, entropy)?;
+    }
 
     let fund_escrow_amount: Uint128 = match offer.offer_type {
         // TODO review this and avoid over-funding by returning diff
@@ -206,6 +366,7 @@ fn fund_escrow(
     };
     if ust_amount >= fund_escrow_amount {
         trade.state = TradeState::EscrowFunded;
+        trade.funded_at = Some(env.block.time.seconds());
     } else {
         return Err(TradeError::FundEscrowError {
             required_amount: fund_escrow_amount.clone(),
@@ -215,6 +376,7 @@ fn fund_escrow(
 
     state_storage(deps.storage).save(&trade).unwrap();
     let res = Response::new()
+        .add_submessage(update_trade_state_msg(&trade))
         .add_attribute("action", "fund_escrow")
         .add_attribute("fund_amount", fund_escrow_amount.to_string())
         .add_attribute("ust_amount", ust_amount.to_string())
@@ -223,6 +385,42 @@ fn fund_escrow(
     Ok(res)
 }
 
+fn provide_entropy(
+    deps: DepsMut,
+    info: MessageInfo,
+    mut trade: TradeData,
+    entropy: Binary,
+) -> Result<Response, TradeError> {
+    let offer = get_offer(&deps.as_ref(), &trade);
+    accept_entropy(&mut trade, &offer, &info.sender, entropy)?;
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = Response::new()
+        .add_attribute("action", "provide_entropy")
+        .add_attribute("maker", info.sender);
+    Ok(res)
+}
+
+// The taker committed the seed, so the entropy it's combined with has to come from the maker.
+fn accept_entropy(
+    trade: &mut TradeData,
+    offer: &Offer,
+    sender: &Addr,
+    entropy: Binary,
+) -> Result<(), TradeError> {
+    if sender != &offer.owner {
+        return Err(TradeError::UnauthorizedEntropy {
+            maker: offer.owner.clone(),
+            caller: sender.clone(),
+        });
+    }
+    if trade.entropy.is_some() | !trade.arbitrator.as_str().is_empty() {
+        return Err(TradeError::EntropyAlreadyProvided {});
+    }
+    trade.entropy = Some(entropy);
+    Ok(())
+}
+
 fn get_offer(deps: &Deps, state: &TradeData) -> Offer {
     deps.querier
         .query(&QueryRequest::Wasm(WasmQuery::Smart {
@@ -235,12 +433,51 @@ fn get_offer(deps: &Deps, state: &TradeData) -> Offer {
         .unwrap()
 }
 
+// The buyer lets the seller know the fiat payment was sent, the seller can't refund anymore.
+fn fiat_deposited(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    mut trade: TradeData,
+) -> Result<Response, TradeError> {
+    assert_not_htlc(&trade)?;
+    if info.sender != trade.buyer {
+        return Err(TradeError::UnauthorizedFiatDeposit {
+            buyer: trade.buyer,
+            caller: info.sender,
+        });
+    }
+    if trade.state != TradeState::EscrowFunded {
+        return Err(TradeError::InvalidStateChange {
+            from: trade.state,
+            to: TradeState::FiatDeposited,
+        });
+    }
+    if env.block.height >= trade.expire_height {
+        return Err(TradeError::Expired {
+            current_height: env.block.height,
+            expire_height: trade.expire_height,
+        });
+    }
+
+    trade.state = TradeState::FiatDeposited;
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = Response::new()
+        .add_submessage(update_trade_state_msg(&trade))
+        .add_attribute("action", "fiat_deposited")
+        .add_attribute("buyer", info.sender);
+    Ok(res)
+}
+
 fn dispute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     state: TradeData,
+    reason: DisputeReason,
 ) -> Result<Response, TradeError> {
+    assert_not_htlc(&state)?;
     if (info.sender != state.seller) & (info.sender != state.buyer) {
         return Err(TradeError::UnauthorizedDispute {
             sender: state.seller,
@@ -249,27 +486,257 @@ fn dispute(
         });
     }
 
+    // Only funded trades can be disputed, up to a while after they expire
+    if (state.state != TradeState::EscrowFunded) & (state.state != TradeState::FiatDeposited) {
+        return Err(TradeError::InvalidStateChange {
+            from: state.state,
+            to: TradeState::Disputed,
+        });
+    }
+    let dispute_window_end = state.expire_height + DISPUTE_WINDOW;
+    if env.block.height > dispute_window_end {
+        return Err(TradeError::DisputeWindowClosed { dispute_window_end });
+    }
+
+    let factory_cfg: FactoryConfig =
+        get_factory_config(&deps.querier, state.factory_addr.to_string());
+    let deposit = get_ust_amount(info.clone());
+    if deposit < factory_cfg.dispute_deposit {
+        return Err(TradeError::DisputeDepositError {
+            required_amount: factory_cfg.dispute_deposit,
+            sent_amount: deposit,
+        });
+    }
+
     // Update trade State to TradeState::Disputed
     let mut trade: TradeData = state_storage(deps.storage).load().unwrap();
 
     trade.state = TradeState::Disputed;
+    trade.disputer = Some(info.sender);
+    trade.dispute_deposit = deposit;
+    trade.dispute_reason = Some(reason);
+    trade.disputed_at = Some(env.block.time.seconds());
+    trade.dispute_deadline = Some(env.block.height + DISPUTE_TIMEOUT);
 
     state_storage(deps.storage).save(&trade).unwrap();
 
-    let res = Response::new();
+    let res = Response::new().add_submessage(update_trade_state_msg(&trade));
+    Ok(res)
+}
+// Fixes the arbitrator from the taker's revealed seed and the entropy given at funding.
+// A taker withholding the seed can't stall a dispute: once the trade expires the
+// commitment stands in for it.
+fn assign_arbitrator(
+    deps: DepsMut,
+    env: Env,
+    mut trade: TradeData,
+    seed: Option<Binary>,
+) -> Result<Response, TradeError> {
+    if !trade.arbitrator.as_str().is_empty() {
+        return Err(TradeError::ArbitratorAlreadyAssigned {
+            arbitrator: trade.arbitrator,
+        });
+    }
+    // Once an expired trade is disputed, whoever withheld their part leaves the pick to the other
+    // party alone, so withholding never helps the one doing it.
+    let withheld = (trade.state == TradeState::Disputed) & (env.block.height > trade.expire_height);
+    let (seed, entropy) = match (seed, trade.entropy.clone()) {
+        (Some(seed), entropy) => {
+            if entropy.is_none() & !withheld {
+                return Err(TradeError::EntropyNotRevealed {});
+            }
+            if Sha256::digest(seed.as_slice()).as_slice() != trade.seed_commitment.as_slice() {
+                return Err(TradeError::InvalidSeed {});
+            }
+            let entropy = entropy.unwrap_or_else(|| seed.clone());
+            (seed, entropy)
+        }
+        (None, Some(entropy)) if withheld => (entropy.clone(), entropy),
+        (None, Some(_)) => return Err(TradeError::SeedNotRevealed {}),
+        (None, None) if withheld => (trade.seed_commitment.clone(), trade.seed_commitment.clone()),
+        (None, None) => return Err(TradeError::EntropyNotRevealed {}),
+    };
+
+    trade.arbitrator = select_arbitrator(&trade.arbitrator_candidates, &seed, &entropy);
+    // The deadline runs from the moment the arbitrator is known
+    if trade.state == TradeState::Disputed {
+        trade.dispute_deadline = Some(env.block.height + DISPUTE_TIMEOUT);
+    }
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = Response::new()
+        .add_submessage(update_trade_state_msg(&trade))
+        .add_attribute("action", "assign_arbitrator")
+        .add_attribute("arbitrator", trade.arbitrator);
     Ok(res)
 }
+
+// Once the deadline of a dispute has passed anyone can hand it to another candidate,
+// the governance stakers vote on it when every candidate has been dismissed.
+fn escalate(deps: DepsMut, env: Env, mut trade: TradeData) -> Result<Response, TradeError> {
+    let factory_cfg: FactoryConfig =
+        get_factory_config(&deps.querier, trade.factory_addr.to_string());
+    if (trade.state != TradeState::Disputed)
+        | (trade.arbitrator == factory_cfg.gov_addr)
+        | trade.ruling.is_some()
+    {
+        return Err(TradeError::CannotEscalate {
+            trade: trade.state.to_string(),
+        });
+    }
+    if trade.arbitrator.as_str().is_empty() {
+        return Err(TradeError::ArbitratorNotAssigned {});
+    }
+    let deadline = trade.dispute_deadline.unwrap_or_default();
+    if env.block.height <= deadline {
+        return Err(TradeError::DisputeDeadlineNotReached { deadline });
+    }
+
+    trade.dismissed_arbitrators.push(trade.arbitrator.clone());
+    let candidates: Vec<Addr> = trade
+        .arbitrator_candidates
+        .iter()
+        .filter(|candidate| !trade.dismissed_arbitrators.contains(candidate))
+        .cloned()
+        .collect();
+    if candidates.is_empty() {
+        trade.arbitrator = factory_cfg.gov_addr;
+        trade.dispute_deadline = None;
+    } else {
+        let entropy = trade.entropy.clone().unwrap_or_default();
+        trade.arbitrator = select_arbitrator(&candidates, &trade.seed_commitment, &entropy);
+        trade.dispute_deadline = Some(env.block.height + DISPUTE_TIMEOUT);
+    }
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = Response::new()
+        .add_submessage(update_trade_state_msg(&trade))
+        .add_attribute("action", "escalate")
+        .add_attribute("arbitrator", trade.arbitrator);
+    Ok(res)
+}
+
+fn select_arbitrator(candidates: &[Addr], seed: &Binary, entropy: &Binary) -> Addr {
+    let hash = Sha256::new()
+        .chain(seed.as_slice())
+        .chain(entropy.as_slice())
+        .finalize();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    let index = u64::from_be_bytes(bytes) % candidates.len() as u64;
+    candidates[index as usize].clone()
+}
+
+const DISPUTE_WINDOW: u64 = 14_400; //Roughly 24h after expiration.
+const DISPUTE_TIMEOUT: u64 = 14_400; //Roughly 24h.
+const APPEAL_WINDOW: u64 = 14_400; //Roughly 24h.
+const HTLC_TIMELOCK: u64 = 14_400; //Roughly 24h, shorter than the counterpart HTLC's.
+const BTC_TIME_DRIFT: u64 = 7_200; //Bitcoin block times can be up to 2h in the future.
+const MAX_MESSAGES: usize = 50;
+const PAYOUT_REPLY_ID: u64 = 0;
+const MAX_MESSAGE_LENGTH: usize = 1024;
+
+fn post_message(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    trade: TradeData,
+    kind: TradeMessageKind,
+    content: String,
+) -> Result<Response, TradeError> {
+    if (info.sender != trade.buyer)
+        & (info.sender != trade.seller)
+        & (info.sender != trade.arbitrator)
+    {
+        return Err(TradeError::UnauthorizedMessage {
+            caller: info.sender,
+        });
+    }
+    if trade.state.is_terminal() {
+        return Err(TradeError::MessagesLocked {
+            trade: trade.state.to_string(),
+        });
+    }
+    if content.len() > MAX_MESSAGE_LENGTH {
+        return Err(TradeError::MessageTooLong {
+            max_length: MAX_MESSAGE_LENGTH,
+        });
+    }
+
+    let mut log = messages(deps.storage).may_load()?.unwrap_or_default();
+    if log.len() >= MAX_MESSAGES {
+        return Err(TradeError::MessageLimit {
+            max_messages: MAX_MESSAGES,
+        });
+    }
+    log.push(TradeMessage {
+        sender: info.sender.clone(),
+        kind,
+        content,
+        timestamp: env.block.time.seconds(),
+    });
+    messages(deps.storage).save(&log)?;
+
+    let res = Response::new()
+        .add_attribute("action", "post_message")
+        .add_attribute("sender", info.sender)
+        .add_attribute("index", (log.len() - 1).to_string());
+    Ok(res)
+}
+
+// The taker's contact comes with the trade, the maker answers with theirs encrypted to the taker.
+fn set_maker_contact(
+    deps: DepsMut,
+    info: MessageInfo,
+    mut trade: TradeData,
+    maker_contact: String,
+) -> Result<Response, TradeError> {
+    let offer = get_offer(&deps.as_ref(), &trade);
+    if info.sender != offer.owner {
+        return Err(TradeError::UnauthorizedContact {
+            maker: offer.owner,
+            caller: info.sender,
+        });
+    }
+    if trade.state.is_terminal() {
+        return Err(TradeError::MessagesLocked {
+            trade: trade.state.to_string(),
+        });
+    }
+    if maker_contact.len() > MAX_MESSAGE_LENGTH {
+        return Err(TradeError::MessageTooLong {
+            max_length: MAX_MESSAGE_LENGTH,
+        });
+    }
+
+    trade.maker_contact = Some(maker_contact);
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = Response::new()
+        .add_attribute("action", "set_maker_contact")
+        .add_attribute("maker", info.sender);
+    Ok(res)
+}
+
 fn release(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     trade: TradeData,
 ) -> Result<Response, TradeError> {
-    let arbitration_mode =
-        (info.sender == trade.arbitrator) & (trade.state == TradeState::Disputed);
+    assert_not_terminal(&trade, TradeState::Closed)?;
+    if (trade.escrow_mode == EscrowMode::Multisig) & (trade.state != TradeState::Created) {
+        return approve(deps, env, info, trade, true);
+    }
+    let factory_cfg: FactoryConfig =
+        get_factory_config(&deps.querier, trade.factory_addr.to_string());
+    if ruling_mode(&info, &trade, &factory_cfg) {
+        return rule(deps, env, trade, true);
+    }
+    let arbitration_mode = governance_mode(&info, &trade, &factory_cfg);
 
     //Check if sender can release
-    if (info.sender != trade.seller) & !arbitration_mode {
+    if ((info.sender != trade.seller) | (trade.state == TradeState::Appealed)) & !arbitration_mode {
         return Err(TradeError::Unauthorized {
             owner: trade.seller,
             arbitrator: trade.arbitrator,
@@ -285,6 +752,75 @@ fn release(
         });
     }
 
+    release_escrow(deps, env, trade, arbitration_mode)
+}
+
+// Releases on behalf of the seller, the relayer only pays the gas.
+fn release_with_signature(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    mut trade: TradeData,
+    nonce: u64,
+    signature: Binary,
+) -> Result<Response, TradeError> {
+    if nonce != trade.release_nonce {
+        return Err(TradeError::InvalidNonce {
+            expected: trade.release_nonce,
+            nonce,
+        });
+    }
+
+    let profile: Option<Profile> = deps.querier.query_wasm_smart(
+        trade.offer_contract.to_string(),
+        &OfferQueryMsg::Profile {
+            address: trade.seller.clone(),
+        },
+    )?;
+    let signing_key =
+        profile
+            .and_then(|profile| profile.signing_key)
+            .ok_or(TradeError::SigningKeyNotFound {
+                seller: trade.seller.clone(),
+            })?;
+
+    let hash = release_authorization_hash(&env.contract.address, &env.block.chain_id, nonce);
+    let verified = deps
+        .api
+        .secp256k1_verify(&hash, &signature, &signing_key)
+        .unwrap_or(false);
+    if !verified {
+        return Err(TradeError::InvalidSignature {});
+    }
+
+    // Signatures can only be used once
+    trade.release_nonce += 1;
+    state_storage(deps.storage).save(&trade)?;
+
+    let seller_info = MessageInfo {
+        sender: trade.seller.clone(),
+        funds: vec![],
+    };
+    let res = release(deps, env, seller_info, trade)?.add_attribute("relayer", info.sender);
+    Ok(res)
+}
+
+pub fn release_authorization_hash(trade: &Addr, chain_id: &str, nonce: u64) -> Vec<u8> {
+    Sha256::new()
+        .chain(trade.as_bytes())
+        .chain(chain_id.as_bytes())
+        .chain(nonce.to_be_bytes())
+        .finalize()
+        .to_vec()
+}
+
+fn release_escrow(
+    deps: DepsMut,
+    env: Env,
+    trade: TradeData,
+    arbitration_mode: bool,
+) -> Result<Response, TradeError> {
+    assert_not_terminal(&trade, TradeState::Closed)?;
     //Load and check balance
     // let balance_result = deps.querier.query_all_balances(&env.contract.address);
     let balance_result = deps.querier.query_balance(&env.contract.address, "uusd");
@@ -309,6 +845,9 @@ fn release(
 
     state_storage(deps.storage).save(&trade).unwrap();
 
+    // An overturned arbitrator doesn't get the arbitration fee
+    let overturned = arbitration_mode & trade.ruling.as_ref().map_or(false, |r| !r.release);
+
     //Calculate fees and final release amount
     let mut send_msgs: Vec<SubMsg> = Vec::new();
 
@@ -332,7 +871,7 @@ fn release(
         let ltfee_tax = compute_tax(&deps.querier, &ltfee_coin).unwrap();
 
         let mut arbitration_fee_inc_tax = Uint128::zero();
-        if arbitration_mode {
+        if arbitration_mode & !overturned {
             // Pay arbitration fee
             let arbitration_rate = 10u128; // TODO move fee to constant
             let arbitration_coin = Coin::new(
@@ -370,10 +909,19 @@ fn release(
         ust
     };
 
-    send_msgs.push(SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-        to_address: trade.buyer.into_string(),
-        amount: vec![release_amount],
-    })));
+    send_msgs.push(payout_msg(deps.storage, &trade, release_amount)?);
+    // The seller may also release a disputed trade, the buyer gets the deposit either way
+    if !trade.dispute_deposit.is_zero() {
+        let deposit = Coin::new(trade.dispute_deposit.u128(), "uusd");
+        send_msgs.push(SubMsg::new(create_send_msg(
+            &deps,
+            trade.buyer.clone(),
+            vec![deposit],
+        )));
+    }
+    if let Some(appeal_bond_msg) = appeal_bond_msg(&deps, &trade, &factory_cfg, overturned) {
+        send_msgs.push(appeal_bond_msg);
+    }
 
     //Create Trade Registration message to be sent to the Trading Incentives contract.
     let register_trade_msg = SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -386,25 +934,159 @@ fn release(
         funds: vec![],
     }));
     send_msgs.push(register_trade_msg);
+    send_msgs.push(update_trade_state_msg(&trade));
 
     let res = Response::new().add_submessages(send_msgs);
     Ok(res)
 }
 
+// Pays the buyer's share of the escrow where the buyer asked for it.
+// Swaps are sent with a reply on error, so a failed swap falls back to sending the UST.
+fn payout_msg(
+    storage: &mut dyn Storage,
+    trade: &TradeData,
+    amount: Coin,
+) -> Result<SubMsg, TradeError> {
+    let (address, swap) = match &trade.payout {
+        Some(payout) => (payout.address.clone(), payout.swap.clone()),
+        None => (trade.buyer.clone(), None),
+    };
+    let swap = match swap {
+        Some(swap) => swap,
+        None => {
+            return Ok(SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: address.to_string(),
+                amount: vec![amount],
+            })))
+        }
+    };
+
+    payout_fallback(storage).save(&amount)?;
+    let swap_msg = Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: amount.amount,
+        },
+        belief_price: swap.belief_price,
+        max_spread: swap.max_spread,
+        to: Some(address.to_string()),
+    };
+    Ok(SubMsg {
+        id: PAYOUT_REPLY_ID,
+        msg: CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: swap.pair_addr.to_string(),
+            msg: to_binary(&swap_msg)?,
+            funds: vec![amount],
+        }),
+        gas_limit: None,
+        reply_on: ReplyOn::Error,
+    })
+}
+
+fn send_payout_fallback(deps: DepsMut) -> Result<Response, TradeError> {
+    let trade = state_read(deps.storage).load()?;
+    let amount = payout_fallback(deps.storage).load()?;
+    payout_fallback(deps.storage).remove();
+    let address = trade.payout.map_or(trade.buyer, |payout| payout.address);
+
+    let res = Response::new()
+        .add_message(CosmosMsg::Bank(BankMsg::Send {
+            to_address: address.to_string(),
+            amount: vec![amount],
+        }))
+        .add_attribute("action", "payout_fallback")
+        .add_attribute("to", address);
+    Ok(res)
+}
+
+// Validates the buyer's payout and finds the Terraswap pair of the asset they want.
+fn load_payout(
+    deps: Deps,
+    factory_addr: &Addr,
+    buyer: &Addr,
+    msg: PayoutMsg,
+) -> Result<Payout, TradeError> {
+    let address = match msg.address {
+        Some(address) => deps.api.addr_validate(&address)?,
+        None => buyer.clone(),
+    };
+    let ask_asset = match msg.ask_asset {
+        Some(ask_asset) => ask_asset,
+        None => {
+            return Ok(Payout {
+                address,
+                swap: None,
+            })
+        }
+    };
+    let ust = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    if ask_asset == ust {
+        return Err(TradeError::InvalidPayout {});
+    }
+
+    let factory_cfg = get_factory_config(&deps.querier, factory_addr.to_string());
+    let pair: PairInfo = deps
+        .querier
+        .query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: factory_cfg.terraswap_factory_addr.to_string(),
+            msg: to_binary(&TerraswapFactoryQueryMsg::Pair {
+                asset_infos: [ust, ask_asset.clone()],
+            })?,
+        }))
+        .map_err(|_| TradeError::PayoutPairNotFound {
+            ask_asset: ask_asset.clone(),
+        })?;
+    Ok(Payout {
+        address,
+        swap: Some(PayoutSwap {
+            pair_addr: deps.api.addr_validate(&pair.contract_addr)?,
+            ask_asset,
+            belief_price: msg.belief_price,
+            max_spread: msg.max_spread,
+        }),
+    })
+}
+
 fn refund(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     trade: TradeData,
 ) -> Result<Response, TradeError> {
-    let arbitration_mode =
-        (info.sender == trade.arbitrator) & (trade.state == TradeState::Disputed);
-
-    // anyone can try to refund, as long as the contract is expired
-    // noone except arbitrator can refund if the trade is in arbitration
-    if (trade.expire_height > env.block.height)
-        & ((trade.state != TradeState::Disputed) & !arbitration_mode)
+    assert_not_terminal(&trade, TradeState::Canceled)?;
+    if (trade.escrow_mode == EscrowMode::Multisig) & (trade.state != TradeState::Created) {
+        return approve(deps, env, info, trade, false);
+    }
+    let factory_cfg: FactoryConfig =
+        get_factory_config(&deps.querier, trade.factory_addr.to_string());
+    if ruling_mode(&info, &trade, &factory_cfg) {
+        return rule(deps, env, trade, false);
+    }
+    let arbitration_mode = governance_mode(&info, &trade, &factory_cfg);
+
+    // noone except arbitrator can refund if the trade is in arbitration,
+    // once the buyer paid the seller has to dispute instead
+    if !arbitration_mode
+        & ((trade.state == TradeState::Disputed)
+            | (trade.state == TradeState::Appealed)
+            | (trade.state == TradeState::FiatDeposited))
     {
+        return Err(TradeError::RefundError {
+            message: "Disputed and fiat deposited trades can only be refunded by the arbitrator."
+                .to_string(),
+            trade: trade.state.to_string(),
+        });
+    }
+    // anyone can try to refund, as long as the contract is expired or past the HTLC timelock
+    let refundable_at = trade
+        .htlc
+        .as_ref()
+        .map_or(trade.expire_height, |htlc| htlc.timelock);
+    if (refundable_at > env.block.height) & !arbitration_mode {
         return Err(TradeError::RefundError {
             message:
                 "Only expired trades that are not disputed can be refunded by non-arbitrators."
@@ -413,6 +1095,16 @@ fn refund(
         });
     }
 
+    refund_escrow(deps, env, trade, arbitration_mode)
+}
+
+fn refund_escrow(
+    deps: DepsMut,
+    env: Env,
+    trade: TradeData,
+    arbitration_mode: bool,
+) -> Result<Response, TradeError> {
+    assert_not_terminal(&trade, TradeState::Canceled)?;
     let balance_result = deps.querier.query_all_balances(&env.contract.address);
     return if balance_result.is_ok() {
         let offer = get_offer(&deps.as_ref(), &trade);
@@ -432,26 +1124,90 @@ fn refund(
 
         // Pay arbitration fee
         if arbitration_mode {
-            let mut balance = balance_result.unwrap();
-
+            let factory_cfg: FactoryConfig =
+                get_factory_config(&deps.querier, trade.factory_addr.to_string());
+            let overturned = trade.ruling.as_ref().map_or(false, |r| r.release);
+            let ust_balance = deps
+                .querier
+                .query_balance(&env.contract.address, "uusd")?
+                .amount;
+
+            // The dispute deposit goes to the seller as well, but no fee is taken from it
+            let refund_amount = ust_balance.checked_sub(trade.appeal_bond).map_err(|_| {
+                TradeError::RefundError {
+                    message: "Contract balance doesn't cover the appeal bond.".to_string(),
+                    trade: trade.state.to_string(),
+                }
+            })?;
+            let escrow_amount = refund_amount
+                .checked_sub(trade.dispute_deposit)
+                .map_err(|_| TradeError::RefundError {
+                    message: "Contract balance doesn't cover the dispute deposit.".to_string(),
+                    trade: trade.state.to_string(),
+                })?;
             let fee_rate: Uint128 = Uint128::new(10);
-            let fee_amount = balance[0].amount.multiply_ratio(Uint128::new(1), fee_rate); // TODO support multiple coins
-            let mut fee = balance.clone();
-            fee[0].amount = fee_amount;
-            balance[0].amount = balance[0].amount - fee_amount;
-
-            let seller_msg = create_send_msg(&deps, trade.seller, balance);
-
-            let arbitrator_msg = create_send_msg(&deps, trade.arbitrator, fee);
+            let fee_amount = if overturned {
+                Uint128::zero()
+            } else {
+                escrow_amount.multiply_ratio(Uint128::new(1), fee_rate)
+            };
+
+            let seller_msg = create_send_msg(
+                &deps,
+                trade.seller.clone(),
+                vec![Coin::new((refund_amount - fee_amount).u128(), "uusd")],
+            );
 
-            let res = Response::new()
-                .add_submessage(SubMsg::new(seller_msg))
-                .add_submessage(SubMsg::new(arbitrator_msg));
+            let mut res = Response::new().add_submessage(SubMsg::new(seller_msg));
+            // Any other coins sent to the contract go back to the seller untouched
+            let other_coins: Vec<Coin> = balance_result
+                .unwrap()
+                .into_iter()
+                .filter(|coin| coin.denom != "uusd")
+                .collect();
+            if !other_coins.is_empty() {
+                let other_msg = create_send_msg(&deps, trade.seller.clone(), other_coins);
+                res = res.add_submessage(SubMsg::new(other_msg));
+            }
+            if !fee_amount.is_zero() {
+                let fee = vec![Coin::new(fee_amount.u128(), "uusd")];
+                let arbitrator_msg = create_send_msg(&deps, trade.arbitrator.clone(), fee);
+                res = res.add_submessage(SubMsg::new(arbitrator_msg));
+            }
+            if let Some(appeal_bond_msg) = appeal_bond_msg(&deps, &trade, &factory_cfg, overturned)
+            {
+                res = res.add_submessage(appeal_bond_msg);
+            }
+            res = res.add_submessage(update_trade_state_msg(&trade));
             Ok(res)
         } else {
-            let balance = balance_result.unwrap();
-            let send_msg = create_send_msg(&deps, trade.seller, balance);
-            let res = Response::new().add_submessage(SubMsg::new(send_msg));
+            let mut balance = balance_result.unwrap();
+            let mut res = Response::new();
+            // Without a ruling the dispute deposit goes back to whoever posted it
+            if let Some(disputer) = trade.disputer.clone() {
+                if !trade.dispute_deposit.is_zero() {
+                    let ust = balance
+                        .iter_mut()
+                        .find(|coin| coin.denom == "uusd")
+                        .filter(|coin| coin.amount >= trade.dispute_deposit)
+                        .ok_or(TradeError::RefundError {
+                            message: "Contract balance doesn't cover the dispute deposit."
+                                .to_string(),
+                            trade: trade.state.to_string(),
+                        })?;
+                    ust.amount -= trade.dispute_deposit;
+                    let deposit = Coin::new(trade.dispute_deposit.u128(), "uusd");
+                    let deposit_msg = create_send_msg(&deps, disputer, vec![deposit]);
+                    res = res.add_submessage(SubMsg::new(deposit_msg));
+                    balance.retain(|coin| !coin.amount.is_zero());
+                }
+            }
+            // Trades that were never funded are just canceled
+            if !balance.is_empty() {
+                let send_msg = create_send_msg(&deps, trade.seller.clone(), balance);
+                res = res.add_submessage(SubMsg::new(send_msg));
+            }
+            res = res.add_submessage(update_trade_state_msg(&trade));
 
             Ok(res)
         }
@@ -463,6 +1219,389 @@ fn refund(
     };
 }
 
+// Pays the buyer of an HTLC trade, anyone holding the preimage can claim for them.
+fn claim(
+    deps: DepsMut,
+    env: Env,
+    mut trade: TradeData,
+    preimage: Binary,
+) -> Result<Response, TradeError> {
+    let mut htlc = trade
+        .htlc
+        .clone()
+        .ok_or(TradeError::UnsupportedEscrowMode {
+            escrow_mode: trade.escrow_mode.clone(),
+        })?;
+    if trade.state != TradeState::EscrowFunded {
+        return Err(TradeError::InvalidStateChange {
+            from: trade.state,
+            to: TradeState::Closed,
+        });
+    }
+    // The seller can refund from the timelock on, so the buyer has to claim before it
+    if env.block.height >= htlc.timelock {
+        return Err(TradeError::Expired {
+            expire_height: htlc.timelock,
+            current_height: env.block.height,
+        });
+    }
+    if Sha256::digest(preimage.as_slice()).as_slice() != htlc.hashlock.as_slice() {
+        return Err(TradeError::InvalidPreimage {});
+    }
+
+    htlc.preimage = Some(preimage);
+    trade.htlc = Some(htlc);
+    state_storage(deps.storage).save(&trade)?;
+
+    release_escrow(deps, env, trade, false)
+}
+
+// The preimage of the invoice's payment hash proves the buyer paid, so anyone can settle with it.
+fn settle_lightning(
+    deps: DepsMut,
+    env: Env,
+    trade: TradeData,
+    preimage: Binary,
+) -> Result<Response, TradeError> {
+    let payment_hash = trade
+        .payment_hash
+        .clone()
+        .ok_or(TradeError::PaymentHashNotSet {})?;
+    if (trade.state != TradeState::EscrowFunded) & (trade.state != TradeState::FiatDeposited) {
+        return Err(TradeError::InvalidStateChange {
+            from: trade.state,
+            to: TradeState::Closed,
+        });
+    }
+    if Sha256::digest(preimage.as_slice()).as_slice() != payment_hash.as_slice() {
+        return Err(TradeError::InvalidPreimage {});
+    }
+
+    let res =
+        release_escrow(deps, env, trade, false)?.add_attribute("preimage", preimage.to_base64());
+    Ok(res)
+}
+
+// An SPV proof of the buyer's Bitcoin payment settles the trade, so anyone can submit it.
+fn prove_btc_payment(
+    deps: DepsMut,
+    env: Env,
+    mut trade: TradeData,
+    tx: Binary,
+    merkle_branch: Vec<Binary>,
+    tx_index: u32,
+    headers: Vec<Binary>,
+) -> Result<Response, TradeError> {
+    let mut btc_payment = trade
+        .btc_payment
+        .clone()
+        .ok_or(TradeError::BtcPaymentNotSet {})?;
+    if (trade.state != TradeState::EscrowFunded) & (trade.state != TradeState::FiatDeposited) {
+        return Err(TradeError::InvalidStateChange {
+            from: trade.state,
+            to: TradeState::Closed,
+        });
+    }
+
+    let factory_cfg = get_factory_config(&deps.querier, trade.factory_addr.to_string());
+    if headers.is_empty() | (headers.len() < factory_cfg.btc_confirmations as usize) {
+        return Err(TradeError::NotEnoughConfirmations {
+            required: factory_cfg.btc_confirmations.max(1),
+            confirmations: headers.len() as u32,
+        });
+    }
+    let headers = headers
+        .iter()
+        .map(|header| BlockHeader::parse(header.as_slice()))
+        .collect::<Result<Vec<BlockHeader>, _>>()?;
+    let pow_limit = bits_to_target(factory_cfg.btc_pow_limit)?;
+    for (index, header) in headers.iter().enumerate() {
+        if (index > 0) && (header.prev_block_hash != headers[index - 1].hash) {
+            return Err(TradeError::BrokenHeaderChain { index });
+        }
+        if (header.target()? > pow_limit) | !header.check_pow()? {
+            return Err(TradeError::InsufficientWork { index });
+        }
+    }
+
+    let transaction = Transaction::parse(tx.as_slice())?;
+    let branch = merkle_branch
+        .iter()
+        .map(|node| {
+            let mut hash = [0u8; 32];
+            if node.len() != 32 {
+                return Err(TradeError::TransactionNotIncluded {});
+            }
+            hash.copy_from_slice(node.as_slice());
+            Ok(hash)
+        })
+        .collect::<Result<Vec<[u8; 32]>, _>>()?;
+    // A 64 byte transaction could pass for an inner merkle node
+    if (tx.len() == 64)
+        | (merkle_root(&transaction.txid, &branch, tx_index) != headers[0].merkle_root)
+    {
+        return Err(TradeError::TransactionNotIncluded {});
+    }
+    let script_pubkey = btc_payment.address.script_pubkey();
+    let paid = transaction
+        .outputs
+        .iter()
+        .any(|output| (output.script_pubkey == script_pubkey) & (output.value >= btc_payment.sats));
+    if !paid {
+        return Err(TradeError::BtcPaymentNotFound {
+            sats: btc_payment.sats,
+        });
+    }
+
+    // Older payments to the same address can't be reused for a new trade
+    let block_time = headers[0].time as u64;
+    let funded_at = trade.funded_at.unwrap_or_default();
+    if block_time + BTC_TIME_DRIFT < funded_at {
+        return Err(TradeError::StaleBtcPayment {
+            block_time,
+            funded_at,
+        });
+    }
+
+    // The headers only prove work, the relay tells whether the block is in the best chain
+    let confirmations: u32 = deps.querier.query_wasm_smart(
+        factory_cfg.btc_relay_addr.to_string(),
+        &BtcRelayQueryMsg::Confirmations {
+            block_hash: headers[0].display_hash(),
+        },
+    )?;
+    if confirmations < factory_cfg.btc_confirmations {
+        return Err(TradeError::NotEnoughConfirmations {
+            required: factory_cfg.btc_confirmations,
+            confirmations,
+        });
+    }
+
+    // The offer contract rejects transactions that already released another trade
+    let txid = to_display_hash(&transaction.txid);
+    btc_payment.txid = Some(txid.clone());
+    trade.btc_payment = Some(btc_payment);
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = release_escrow(deps, env, trade, false)?
+        .add_attribute("btc_block", headers[0].display_hash())
+        .add_attribute("btc_txid", txid);
+    Ok(res)
+}
+
+// Finished trades already paid out, they can't be released or refunded again.
+fn assert_not_terminal(trade: &TradeData, to: TradeState) -> Result<(), TradeError> {
+    if trade.state.is_terminal() {
+        return Err(TradeError::InvalidStateChange {
+            from: trade.state.clone(),
+            to,
+        });
+    }
+    Ok(())
+}
+
+// HTLC trades only settle through the hashlock or the timelock.
+fn assert_not_htlc(trade: &TradeData) -> Result<(), TradeError> {
+    if trade.escrow_mode == EscrowMode::Htlc {
+        return Err(TradeError::UnsupportedEscrowMode {
+            escrow_mode: trade.escrow_mode.clone(),
+        });
+    }
+    Ok(())
+}
+
+// Records the sender's approval of a payout, two distinct approvals of the same outcome settle it.
+fn approve(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    mut trade: TradeData,
+    release: bool,
+) -> Result<Response, TradeError> {
+    if !matches!(
+        trade.state,
+        TradeState::EscrowFunded | TradeState::FiatDeposited | TradeState::Disputed
+    ) {
+        return Err(TradeError::InvalidStateChange {
+            from: trade.state,
+            to: if release {
+                TradeState::Closed
+            } else {
+                TradeState::Canceled
+            },
+        });
+    }
+    // The arbitrator is empty until it's assigned
+    let party = info.sender;
+    if (party != trade.buyer) & (party != trade.seller) & (party != trade.arbitrator) {
+        return Err(TradeError::Unauthorized {
+            owner: trade.seller,
+            arbitrator: trade.arbitrator,
+            caller: party,
+        });
+    }
+
+    let (approvals, other_approvals) = if release {
+        (&mut trade.release_approvals, &mut trade.refund_approvals)
+    } else {
+        (&mut trade.refund_approvals, &mut trade.release_approvals)
+    };
+    if approvals.contains(&party) {
+        return Err(TradeError::AlreadyApproved { party });
+    }
+    // Parties can change their mind until the payout happens
+    other_approvals.retain(|approver| approver != &party);
+    approvals.push(party.clone());
+
+    let approved = approvals.len() >= 2;
+    let arbitration_mode = approvals.contains(&trade.arbitrator);
+    state_storage(deps.storage).save(&trade)?;
+
+    if !approved {
+        let res = Response::new()
+            .add_attribute("action", "approve")
+            .add_attribute("party", party)
+            .add_attribute("release", release.to_string());
+        return Ok(res);
+    }
+    if release {
+        release_escrow(deps, env, trade, arbitration_mode)
+    } else {
+        refund_escrow(deps, env, trade, arbitration_mode)
+    }
+}
+
+// The assigned arbitrator rules on the dispute, governance can't be appealed and settles right away.
+fn ruling_mode(info: &MessageInfo, trade: &TradeData, factory_cfg: &FactoryConfig) -> bool {
+    (info.sender == trade.arbitrator)
+        & (trade.state == TradeState::Disputed)
+        & (trade.arbitrator != factory_cfg.gov_addr)
+}
+
+// Governance settles disputes escalated to it and appealed rulings.
+fn governance_mode(info: &MessageInfo, trade: &TradeData, factory_cfg: &FactoryConfig) -> bool {
+    (info.sender == factory_cfg.gov_addr)
+        & ((trade.state == TradeState::Appealed)
+            | ((trade.state == TradeState::Disputed) & (trade.arbitrator == factory_cfg.gov_addr)))
+}
+
+fn rule(
+    deps: DepsMut,
+    env: Env,
+    mut trade: TradeData,
+    release: bool,
+) -> Result<Response, TradeError> {
+    if let Some(ruling) = trade.ruling {
+        return Err(TradeError::AlreadyRuled {
+            appeal_deadline: ruling.appeal_deadline,
+        });
+    }
+    // Late arbitrators are replaced instead, so their ruling falls within the registry's unbonding
+    let deadline = trade.dispute_deadline.unwrap_or_default();
+    if env.block.height > deadline {
+        return Err(TradeError::DisputeDeadlinePassed { deadline });
+    }
+    let appeal_deadline = env.block.height + APPEAL_WINDOW;
+    trade.ruling = Some(Ruling {
+        release,
+        appeal_deadline,
+    });
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = Response::new()
+        .add_attribute("action", "rule")
+        .add_attribute("release", release.to_string())
+        .add_attribute("appeal_deadline", appeal_deadline.to_string());
+    Ok(res)
+}
+
+// The losing party bonds UST to have governance vote on the ruling, the payout is held meanwhile.
+fn appeal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    mut trade: TradeData,
+) -> Result<Response, TradeError> {
+    let ruling = trade.ruling.clone().ok_or(TradeError::NoRuling {})?;
+    if trade.state != TradeState::Disputed {
+        return Err(TradeError::RulingAppealed {});
+    }
+    let losing_party = if ruling.release {
+        &trade.seller
+    } else {
+        &trade.buyer
+    };
+    if &info.sender != losing_party {
+        return Err(TradeError::UnauthorizedAppeal {
+            caller: info.sender,
+        });
+    }
+    if env.block.height > ruling.appeal_deadline {
+        return Err(TradeError::AppealWindowClosed {
+            appeal_deadline: ruling.appeal_deadline,
+        });
+    }
+
+    let factory_cfg: FactoryConfig =
+        get_factory_config(&deps.querier, trade.factory_addr.to_string());
+    let bond = get_ust_amount(info.clone());
+    if bond < factory_cfg.appeal_bond {
+        return Err(TradeError::AppealBondError {
+            required_amount: factory_cfg.appeal_bond,
+            sent_amount: bond,
+        });
+    }
+
+    trade.state = TradeState::Appealed;
+    trade.appellant = Some(info.sender.clone());
+    trade.appeal_bond = bond;
+    state_storage(deps.storage).save(&trade)?;
+
+    let res = Response::new()
+        .add_submessage(update_trade_state_msg(&trade))
+        .add_attribute("action", "appeal")
+        .add_attribute("appellant", info.sender)
+        .add_attribute("appeal_bond", bond);
+    Ok(res)
+}
+
+// Carries out a ruling nobody appealed once the appeal window is over.
+fn execute_ruling(deps: DepsMut, env: Env, trade: TradeData) -> Result<Response, TradeError> {
+    let ruling = trade.ruling.clone().ok_or(TradeError::NoRuling {})?;
+    if trade.state != TradeState::Disputed {
+        return Err(TradeError::RulingAppealed {});
+    }
+    if env.block.height <= ruling.appeal_deadline {
+        return Err(TradeError::AppealWindowOpen {
+            appeal_deadline: ruling.appeal_deadline,
+        });
+    }
+
+    if ruling.release {
+        release_escrow(deps, env, trade, true)
+    } else {
+        refund_escrow(deps, env, trade, true)
+    }
+}
+
+// Overturned rulings return the bond to the appellant, otherwise it's collected as a fee.
+fn appeal_bond_msg(
+    deps: &DepsMut,
+    trade: &TradeData,
+    factory_cfg: &FactoryConfig,
+    overturned: bool,
+) -> Option<SubMsg> {
+    if trade.appeal_bond.is_zero() {
+        return None;
+    }
+    let recipient = match (&trade.appellant, overturned) {
+        (Some(appellant), true) => appellant.clone(),
+        _ => factory_cfg.fee_collector_addr.clone(),
+    };
+    let bond = Coin::new(trade.appeal_bond.u128(), "uusd");
+    Some(SubMsg::new(create_send_msg(deps, recipient, vec![bond])))
+}
+
 fn get_ust_amount(info: MessageInfo) -> Uint128 {
     let ust = &info.funds.iter().find(|c| c.denom.eq("uusd"));
     return match ust {
@@ -475,6 +1614,15 @@ pub fn localterra_fee(amount: Uint128) -> Uint128 {
     amount.clone().checked_div(Uint128::new(100u128)).unwrap()
 }
 
+// Lets the offer contract keep its trade index and counters in sync with this trade.
+fn update_trade_state_msg(trade: &TradeData) -> SubMsg {
+    SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: trade.offer_contract.to_string(),
+        msg: to_binary(&OfferMsg::UpdateTradeState {}).unwrap(),
+        funds: vec![],
+    }))
+}
+
 fn create_send_msg(deps: &DepsMut, to_address: Addr, coins: Vec<Coin>) -> CosmosMsg {
     let mut coins_without_tax: Vec<Coin> = Vec::new();
     coins
@@ -1,10 +1,16 @@
 use cosmwasm_std::{Addr, StdError, Uint128};
+use localterra_protocol::bitcoin::Sats;
+use localterra_protocol::errors::BitcoinError;
+use localterra_protocol::trade::{EscrowMode, TradeState};
+use terraswap::asset::AssetInfo;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum TradeError {
     #[error("{0}")]
     Std(#[from] StdError),
+    #[error("{0}")]
+    Bitcoin(#[from] BitcoinError),
     #[error("Amount is outside of offer amount range.")]
     AmountError {
         amount: Uint128,
@@ -36,18 +42,120 @@ pub enum TradeError {
         required_amount: Uint128,
         sent_amount: Uint128,
     },
+    #[error("Dispute deposit error.")]
+    DisputeDepositError {
+        required_amount: Uint128,
+        sent_amount: Uint128,
+    },
     #[error("Escrow already funded.")]
     AlreadyFundedError {},
+    #[error("Seed commitment must be a sha256 hash.")]
+    InvalidSeedCommitment {},
+    #[error("Seed doesn't match the commitment.")]
+    InvalidSeed {},
+    #[error("The maker hasn't provided entropy yet.")]
+    EntropyNotRevealed {},
+    #[error("Only the maker can provide entropy, the taker committed the seed.")]
+    UnauthorizedEntropy { maker: Addr, caller: Addr },
+    #[error("Entropy already provided.")]
+    EntropyAlreadyProvided {},
+    #[error("The taker must reveal the seed.")]
+    SeedNotRevealed {},
+    #[error("Arbitrator already assigned.")]
+    ArbitratorAlreadyAssigned { arbitrator: Addr },
+    #[error("Arbitrator not assigned yet.")]
+    ArbitratorNotAssigned {},
+    #[error("Only disputes that are still with an arbitrator can be escalated.")]
+    CannotEscalate { trade: String },
+    #[error("The arbitrator still has time to settle the dispute.")]
+    DisputeDeadlineNotReached { deadline: u64 },
+    #[error("The dispute deadline has passed, the dispute can only be escalated.")]
+    DisputeDeadlinePassed { deadline: u64 },
     #[error("Unauthorized.")]
     Unauthorized {
         owner: Addr,
         arbitrator: Addr,
         caller: Addr,
     },
+    #[error("Only the buyer can mark the fiat as deposited.")]
+    UnauthorizedFiatDeposit { buyer: Addr, caller: Addr },
+    #[error("Invalid trade state change.")]
+    InvalidStateChange { from: TradeState, to: TradeState },
+    #[error("The dispute window is closed.")]
+    DisputeWindowClosed { dispute_window_end: u64 },
+    #[error("Invalid release authorisation nonce.")]
+    InvalidNonce { expected: u64, nonce: u64 },
+    #[error("The seller has no signing key in their profile.")]
+    SigningKeyNotFound { seller: Addr },
+    #[error("Invalid release authorisation signature.")]
+    InvalidSignature {},
+    #[error("Hashlock must be a sha256 hash.")]
+    InvalidHashlock {},
+    #[error("Payment hash must be a sha256 hash.")]
+    InvalidPaymentHash {},
+    #[error("Trade isn't paid over Lightning.")]
+    PaymentHashNotSet {},
+    #[error("Preimage doesn't match the hash.")]
+    InvalidPreimage {},
+    #[error("Bitcoin offers need a payout address and a valid sats amount.")]
+    InvalidBtcPayment {},
+    #[error("Trade isn't paid in Bitcoin.")]
+    BtcPaymentNotSet {},
+    #[error("The seller has to confirm the sats amount of the trade.")]
+    BtcAmountMismatch { sats: Sats, confirmed: Option<Sats> },
+    #[error("Not enough confirmations.")]
+    NotEnoughConfirmations { required: u32, confirmations: u32 },
+    #[error("Block headers don't form a chain.")]
+    BrokenHeaderChain { index: usize },
+    #[error("Block header doesn't have enough proof of work.")]
+    InsufficientWork { index: usize },
+    #[error("Transaction isn't included in the block.")]
+    TransactionNotIncluded {},
+    #[error("Transaction doesn't pay the seller.")]
+    BtcPaymentNotFound { sats: Sats },
+    #[error("Bitcoin payment predates the escrow.")]
+    StaleBtcPayment { block_time: u64, funded_at: u64 },
+    #[error("Only the buyer can set a payout, in an asset other than UST.")]
+    InvalidPayout {},
+    #[error("No Terraswap UST pair for the payout asset.")]
+    PayoutPairNotFound { ask_asset: AssetInfo },
+    #[error("Invalid reply message id.")]
+    InvalidReply {},
+    #[error("Not supported in this escrow mode.")]
+    UnsupportedEscrowMode { escrow_mode: EscrowMode },
+    #[error("Payout already approved.")]
+    AlreadyApproved { party: Addr },
+    #[error("The arbitrator already ruled on this dispute.")]
+    AlreadyRuled { appeal_deadline: u64 },
+    #[error("The arbitrator hasn't ruled on this dispute.")]
+    NoRuling {},
+    #[error("The ruling was appealed, governance settles the trade.")]
+    RulingAppealed {},
+    #[error("Only the losing party can appeal.")]
+    UnauthorizedAppeal { caller: Addr },
+    #[error("The appeal window is closed.")]
+    AppealWindowClosed { appeal_deadline: u64 },
+    #[error("The ruling can still be appealed.")]
+    AppealWindowOpen { appeal_deadline: u64 },
+    #[error("Appeal bond error.")]
+    AppealBondError {
+        required_amount: Uint128,
+        sent_amount: Uint128,
+    },
     #[error("Unauthorized Dispute.")]
     UnauthorizedDispute {
         sender: Addr,
         recipient: Addr,
         caller: Addr,
     },
+    #[error("Only the buyer, the seller and the arbitrator can post messages.")]
+    UnauthorizedMessage { caller: Addr },
+    #[error("Only the maker can set their contact.")]
+    UnauthorizedContact { maker: Addr, caller: Addr },
+    #[error("Trade is finished, no more messages can be posted.")]
+    MessagesLocked { trade: String },
+    #[error("Message is too long.")]
+    MessageTooLong { max_length: usize },
+    #[error("Trade message limit reached.")]
+    MessageLimit { max_messages: usize },
 }
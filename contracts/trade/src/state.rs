@@ -1,8 +1,10 @@
-use cosmwasm_std::Storage;
+use cosmwasm_std::{Coin, Storage};
 use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
-use localterra_protocol::trade::TradeData;
+use localterra_protocol::trade::{TradeData, TradeMessage};
 
 pub static STATE_KEY: &[u8] = b"state";
+pub static MESSAGES_KEY: &[u8] = b"messages";
+pub static PAYOUT_FALLBACK_KEY: &[u8] = b"payout_fallback";
 
 pub fn state(storage: &mut dyn Storage) -> Singleton<TradeData> {
     singleton(storage, STATE_KEY)
@@ -11,3 +13,16 @@ pub fn state(storage: &mut dyn Storage) -> Singleton<TradeData> {
 pub fn state_read(storage: &dyn Storage) -> ReadonlySingleton<TradeData> {
     singleton_read(storage, STATE_KEY)
 }
+
+pub fn messages(storage: &mut dyn Storage) -> Singleton<Vec<TradeMessage>> {
+    singleton(storage, MESSAGES_KEY)
+}
+
+pub fn messages_read(storage: &dyn Storage) -> ReadonlySingleton<Vec<TradeMessage>> {
+    singleton_read(storage, MESSAGES_KEY)
+}
+
+// UST sent instead if the payout swap fails
+pub fn payout_fallback(storage: &mut dyn Storage) -> Singleton<Coin> {
+    singleton(storage, PAYOUT_FALLBACK_KEY)
+}
@@ -2,21 +2,53 @@
 
 use std::ops::Add;
 
-use cosmwasm_std::testing::{MockApi, MockStorage};
+use cosmwasm_std::testing::{MockApi, MockStorage, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    from_binary, Addr, BankMsg, Coin, CosmosMsg, DepsMut, Empty, MessageInfo, OwnedDeps, Response,
-    SubMsg, Uint128,
+    attr, from_binary, to_binary, Addr, BankMsg, Binary, Coin, ContractResult, CosmosMsg, Decimal,
+    DepsMut, Empty, MessageInfo, OwnedDeps, Reply, ReplyOn, Response, SubMsg, Uint128, WasmMsg,
 };
 use cosmwasm_vm::testing::{mock_env, mock_info};
+use sha2::{Digest, Sha256};
 
+use localterra_protocol::bitcoin::Sats;
 use localterra_protocol::currencies::FiatCurrency;
-use localterra_protocol::offer::{Offer, OfferState, OfferType};
-use localterra_protocol::trade::{ExecuteMsg, InstantiateMsg, QueryMsg, TradeData, TradeState};
+use localterra_protocol::offer::{
+    ExecuteMsg as OfferExecuteMsg, Offer, OfferState, OfferType, PaymentMethod,
+};
+use localterra_protocol::trade::{
+    DisputeReason, EscrowMode, ExecuteMsg, InstantiateMsg, PayoutMsg, QueryMsg, TradeData,
+    TradeMessage, TradeMessageKind, TradeState,
+};
+use terraswap::asset::{Asset, AssetInfo};
+use terraswap::pair::ExecuteMsg as PairExecuteMsg;
 
-use crate::contract::{execute, instantiate, localterra_fee, query};
+use crate::contract::{execute, instantiate, localterra_fee, query, reply};
 use crate::errors::TradeError;
 use crate::mock_querier::{mock_dependencies, WasmMockQuerier};
 
+fn arbitrators() -> Vec<String> {
+    vec![
+        "arbitrator".to_string(),
+        "arbitrator2".to_string(),
+        "arbitrator3".to_string(),
+    ]
+}
+
+fn seed_commitment() -> Binary {
+    Binary::from(Sha256::digest(b"seed").to_vec())
+}
+
+fn query_trade(deps: &OwnedDeps<MockStorage, MockApi, WasmMockQuerier>) -> TradeData {
+    from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap()
+}
+
+fn hex_decode(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
 #[test]
 fn test_init() {
     let mut deps = mock_dependencies(&[], None);
@@ -25,9 +57,14 @@ fn test_init() {
 
     let instantiate_trade_msg = InstantiateMsg {
         offer_id: 1,
-        arbitrator: "arbitrator".to_string(),
+        arbitrators: arbitrators(),
         taker_contact: "USTKing".to_string(),
-        ust_amount: trade_amount.clone().to_string(),
+        seed_commitment: seed_commitment(),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+        ust_amount: trade_amount.clone(),
         taker: "other".to_string(),
         offers_addr: "offers".to_string(),
         timestamp: 1641329895,
@@ -56,9 +93,14 @@ fn create_trade(
     //Init trade
     let instantiate_trade_msg = InstantiateMsg {
         offer_id: 1,
-        arbitrator: "arbitrator".to_string(),
+        arbitrators: arbitrators(),
         taker_contact: "USTKing".to_string(),
-        ust_amount: trade_amount.clone().to_string(),
+        seed_commitment: seed_commitment(),
+        hashlock: None,
+        payment_hash: None,
+        btc_amount: None,
+        payout: None,
+        ust_amount: trade_amount.clone(),
         taker: info.sender.clone().into_string(),
         offers_addr: "offers".to_string(),
         timestamp: 1641329895,
@@ -82,6 +124,25 @@ fn mock_info_with_ust(sender: &str, amount: Uint128) -> MessageInfo {
     )
 }
 
+fn fund_escrow_msg() -> ExecuteMsg {
+    ExecuteMsg::FundEscrow {
+        entropy: None,
+        btc_amount: None,
+    }
+}
+
+fn provide_entropy(deps: DepsMut) {
+    execute(
+        deps,
+        mock_env(),
+        mock_info("offer-owner", &[]),
+        ExecuteMsg::ProvideEntropy {
+            entropy: Binary::from(b"entropy".to_vec()),
+        },
+    )
+    .unwrap();
+}
+
 fn release_trade(deps: DepsMut, info: MessageInfo) -> Result<Response<Empty>, TradeError> {
     let res = execute(deps, mock_env(), info.clone(), ExecuteMsg::Release {});
     assert!(&res.is_ok());
@@ -173,6 +234,13 @@ fn create_offer_struct(
         max_amount: max_amount.clone(),
         state: OfferState::Active,
         timestamp: 1641329895,
+        expires_at: None,
+        max_open_trades: None,
+        taker_cooldown: None,
+        taker_requirements: None,
+        escrow_mode: None,
+        payment_method: None,
+        btc_payout_address: None,
     }
 }
 
@@ -207,6 +275,30 @@ fn test_trade_amount() {
     assert!(res.is_err());
 }
 
+///Only the seller can fund the escrow when taking an offer.
+#[test]
+fn test_fund_at_creation() {
+    let trade_amount = Uint128::new(1_000_000u128);
+    let taker = mock_info_with_ust("taker", trade_amount);
+
+    //The taker of a buy offer is the seller and can fund right away
+    let (res, deps) = create_trade(trade_amount, taker.clone(), None);
+    assert!(res.is_ok());
+    assert_eq!(query_trade(&deps).state, TradeState::EscrowFunded);
+
+    //The taker of a sell offer is the buyer and can't fund the escrow
+    let offer = create_offer_struct(trade_amount, trade_amount, Some(OfferType::Sell), None);
+    let (res, _) = create_trade(trade_amount, taker.clone(), Some(offer.clone()));
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::Unauthorized { owner, caller, .. }
+            if (owner == Addr::unchecked("offer-owner")) & (caller == taker.sender)
+    ));
+    let (res, deps) = create_trade(trade_amount, mock_info("taker", &[]), Some(offer));
+    assert!(res.is_ok());
+    assert_eq!(query_trade(&deps).state, TradeState::Created);
+}
+
 ///Test trade expiration.
 #[test]
 fn test_trade_expiration() {
@@ -285,22 +377,57 @@ fn test_refund() {
     //Try to refund and assert it's ok.
     let res = execute(
         deps.as_mut(),
-        expired_env,
+        expired_env.clone(),
         any_info.clone(),
         ExecuteMsg::Refund {},
     );
     assert!(res.is_ok());
+
     //Verify that the correct messages were sent after trade completion
     assert_eq!(
         res.unwrap().messages,
-        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-            to_address: info.sender.to_string(),
-            amount: vec![Coin {
-                denom: "uusd".to_string(),
-                amount: trade_amount.clone(),
-            }],
-        }))]
-    )
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: trade_amount.clone(),
+                }],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "offers".to_string(),
+                msg: to_binary(&OfferExecuteMsg::UpdateTradeState {}).unwrap(),
+                funds: vec![],
+            }))
+        ]
+    );
+
+    //Finished trades can't be refunded or released again
+    for msg in vec![ExecuteMsg::Refund {}, ExecuteMsg::Release {}] {
+        let res = execute(deps.as_mut(), expired_env.clone(), info.clone(), msg);
+        assert!(matches!(
+            res.err().unwrap(),
+            TradeError::InvalidStateChange {
+                from: TradeState::Canceled,
+                ..
+            }
+        ));
+    }
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    release_trade(deps.as_mut(), info.clone()).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        expired_env.clone(),
+        any_info.clone(),
+        ExecuteMsg::Refund {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidStateChange {
+            from: TradeState::Closed,
+            to: TradeState::Canceled
+        }
+    ));
 }
 
 ///Test fund escrow after instantiating Trade without coins
@@ -320,23 +447,40 @@ fn test_fund_escrow() {
     trade_amount = trade_amount.add(localterra_fee);
     info.funds[0].amount = trade_amount.clone();
 
+    //Only the seller funds the escrow
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust("offer-owner", trade_amount),
+        fund_escrow_msg(),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::Unauthorized { .. }
+    ));
+
+    //The seller is the taker here, who already committed the seed
     let res = execute(
         deps.as_mut(),
         mock_env(),
         info.clone(),
-        ExecuteMsg::FundEscrow {},
+        ExecuteMsg::FundEscrow {
+            entropy: Some(Binary::from(b"entropy".to_vec())),
+            btc_amount: None,
+        },
     );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::UnauthorizedEntropy { .. }
+    ));
+
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), fund_escrow_msg());
     assert!(res.is_ok());
     let trade_state: TradeData =
         from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
     assert_eq!(trade_state.state, TradeState::EscrowFunded);
     // Funding trade a second time will throw an error.
-    let res_error = execute(
-        deps.as_mut(),
-        mock_env(),
-        info.clone(),
-        ExecuteMsg::FundEscrow {},
-    );
+    let res_error = execute(deps.as_mut(), mock_env(), info.clone(), fund_escrow_msg());
 
     println!("{:?}", res_error);
 
@@ -360,7 +504,1535 @@ fn test_expired_trade() {
     let mut env = mock_env();
     env.block.height = trade_state.expire_height;
 
-    let res = execute(deps.as_mut(), env, info.clone(), ExecuteMsg::FundEscrow {});
+    let res = execute(deps.as_mut(), env, info.clone(), fund_escrow_msg());
     assert!(res.is_err());
     assert!(matches!(res.err().unwrap(), TradeError::Expired { .. }));
 }
+
+#[test]
+fn test_trade_messages() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+
+    let post_message = |kind: TradeMessageKind, content: &str| ExecuteMsg::PostMessage {
+        kind,
+        content: content.to_string(),
+    };
+
+    //Only trade participants can post
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        post_message(TradeMessageKind::Message, "hello"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::UnauthorizedMessage { .. }
+    ));
+
+    //Messages have a size limit
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        post_message(TradeMessageKind::Message, &"a".repeat(1025)),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::MessageTooLong { .. }
+    ));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        post_message(TradeMessageKind::Message, "encrypted-hello"),
+    )
+    .unwrap();
+    provide_entropy(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        ExecuteMsg::AssignArbitrator {
+            seed: Some(Binary::from(b"seed".to_vec())),
+        },
+    )
+    .unwrap();
+    let trade: TradeData =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.arbitrator.as_str(), &[]),
+        post_message(TradeMessageKind::Evidence, "receipt-hash"),
+    )
+    .unwrap();
+
+    let messages: Vec<TradeMessage> =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Messages {}).unwrap()).unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].sender, Addr::unchecked("taker"));
+    assert_eq!(messages[1].kind, TradeMessageKind::Evidence);
+
+    //The log is locked once the trade is finished
+    release_trade(deps.as_mut(), info.clone()).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        post_message(TradeMessageKind::Message, "bye"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::MessagesLocked { .. }
+    ));
+}
+
+#[test]
+fn test_maker_contact() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    let set_contact = |maker_contact: &str| ExecuteMsg::SetMakerContact {
+        maker_contact: maker_contact.to_string(),
+    };
+
+    //Only the maker can post their contact
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        set_contact("encrypted-contact"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::UnauthorizedContact { .. }
+    ));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offer-owner", &[]),
+        set_contact("encrypted-contact"),
+    )
+    .unwrap();
+    let trade: TradeData =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    assert_eq!(trade.maker_contact, Some("encrypted-contact".to_string()));
+
+    //It can't change once the trade is finished
+    release_trade(deps.as_mut(), info.clone()).unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offer-owner", &[]),
+        set_contact("other-contact"),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::MessagesLocked { .. }
+    ));
+}
+
+#[test]
+fn test_assign_arbitrator() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let mut info = mock_info_with_ust("taker", Uint128::zero());
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    let assign = |seed: Option<&str>| ExecuteMsg::AssignArbitrator {
+        seed: seed.map(|seed| Binary::from(seed.as_bytes().to_vec())),
+    };
+
+    //The seed can't be revealed before the maker provides its entropy
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        assign(Some("seed")),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::EntropyNotRevealed {}
+    ));
+
+    info.funds[0].amount = trade_amount.add(localterra_fee(trade_amount));
+    execute(deps.as_mut(), mock_env(), info.clone(), fund_escrow_msg()).unwrap();
+    let trade: TradeData =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    assert_eq!(trade.arbitrator, Addr::unchecked(""));
+
+    //The entropy comes from the maker, only once
+    let provide_msg = ExecuteMsg::ProvideEntropy {
+        entropy: Binary::from(b"other-entropy".to_vec()),
+    };
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), provide_msg.clone());
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::UnauthorizedEntropy { .. }
+    ));
+    provide_entropy(deps.as_mut());
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offer-owner", &[]),
+        provide_msg,
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::EntropyAlreadyProvided {}
+    ));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        assign(Some("other-seed")),
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::InvalidSeed {}));
+
+    //Falling back to the commitment is only possible for expired disputes
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), assign(None));
+    assert!(matches!(res.err().unwrap(), TradeError::SeedNotRevealed {}));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        assign(Some("seed")),
+    )
+    .unwrap();
+    let trade: TradeData =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    let hash = Sha256::new().chain(b"seed").chain(b"entropy").finalize();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    let index = u64::from_be_bytes(bytes) % 3;
+    assert_eq!(
+        trade.arbitrator,
+        Addr::unchecked(&arbitrators()[index as usize])
+    );
+
+    let res = execute(deps.as_mut(), mock_env(), info, assign(Some("seed")));
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::ArbitratorAlreadyAssigned { .. }
+    ));
+}
+
+#[test]
+fn test_assign_arbitrator_withheld_seed() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    let trade = query_trade(&deps);
+    provide_entropy(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReleased,
+        },
+    )
+    .unwrap();
+
+    //Withholding the seed leaves the pick to the maker's entropy
+    let mut env = mock_env();
+    env.block.height = trade.expire_height + 1;
+    execute(
+        deps.as_mut(),
+        env,
+        mock_info("any", &[]),
+        ExecuteMsg::AssignArbitrator { seed: None },
+    )
+    .unwrap();
+    let hash = Sha256::new().chain(b"entropy").chain(b"entropy").finalize();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    let index = u64::from_be_bytes(bytes) % 3;
+    assert_eq!(
+        query_trade(&deps).arbitrator,
+        Addr::unchecked(&arbitrators()[index as usize])
+    );
+}
+
+#[test]
+fn test_assign_arbitrator_withheld_entropy() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    let trade = query_trade(&deps);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReleased,
+        },
+    )
+    .unwrap();
+
+    //Withholding the entropy leaves the pick to the taker's seed
+    let mut env = mock_env();
+    env.block.height = trade.expire_height + 1;
+    execute(
+        deps.as_mut(),
+        env,
+        info,
+        ExecuteMsg::AssignArbitrator {
+            seed: Some(Binary::from(b"seed".to_vec())),
+        },
+    )
+    .unwrap();
+    let hash = Sha256::new().chain(b"seed").chain(b"seed").finalize();
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash[..8]);
+    let index = u64::from_be_bytes(bytes) % 3;
+    assert_eq!(
+        query_trade(&deps).arbitrator,
+        Addr::unchecked(&arbitrators()[index as usize])
+    );
+}
+
+#[test]
+fn test_escalate_dispute() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    provide_entropy(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::AssignArbitrator {
+            seed: Some(Binary::from(b"seed".to_vec())),
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReleased,
+        },
+    )
+    .unwrap();
+    let trade = query_trade(&deps);
+    let deadline = trade.dispute_deadline.unwrap();
+
+    //The arbitrator has until the deadline to settle the dispute
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        ExecuteMsg::Escalate {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::DisputeDeadlineNotReached { .. }
+    ));
+
+    //Past the deadline the arbitrator can't rule anymore, only be replaced
+    let mut late_env = mock_env();
+    late_env.block.height = deadline + 1;
+    let res = execute(
+        deps.as_mut(),
+        late_env,
+        mock_info(trade.arbitrator.as_str(), &[]),
+        ExecuteMsg::Release {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::DisputeDeadlinePassed { .. }
+    ));
+
+    //Every candidate gets a turn before governance takes over
+    let mut env = mock_env();
+    let mut dismissed = vec![];
+    for _ in 0..arbitrators().len() {
+        let trade = query_trade(&deps);
+        env.block.height = trade.dispute_deadline.unwrap_or(deadline) + 1;
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("any", &[]),
+            ExecuteMsg::Escalate {},
+        )
+        .unwrap();
+        dismissed.push(trade.arbitrator.clone());
+
+        let escalated = query_trade(&deps);
+        assert_eq!(escalated.dismissed_arbitrators, dismissed);
+        assert!(!dismissed.contains(&escalated.arbitrator));
+    }
+    let trade = query_trade(&deps);
+    assert_eq!(trade.arbitrator, Addr::unchecked("gov"));
+    assert_eq!(trade.dispute_deadline, None);
+
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("any", &[]),
+        ExecuteMsg::Escalate {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::CannotEscalate { .. }
+    ));
+}
+
+#[test]
+fn test_dispute_deposit() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    provide_entropy(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::AssignArbitrator {
+            seed: Some(Binary::from(b"seed".to_vec())),
+        },
+    )
+    .unwrap();
+
+    //Disputes require the deposit
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &[]),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReleased,
+        },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::DisputeDepositError { .. }
+    ));
+
+    let deposit = Uint128::new(1_000_000u128);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust("taker", deposit),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReleased,
+        },
+    )
+    .unwrap();
+    let trade: TradeData =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    assert_eq!(trade.dispute_deposit, deposit);
+
+    //Releasing only records the ruling until the appeal window is over
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.arbitrator.as_str(), &[]),
+        ExecuteMsg::Release {},
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    let trade = query_trade(&deps);
+    let ruling = trade.ruling.clone().unwrap();
+    assert!(ruling.release);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.arbitrator.as_str(), &[]),
+        ExecuteMsg::Refund {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::AlreadyRuled { .. }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        ExecuteMsg::ExecuteRuling {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::AppealWindowOpen { .. }
+    ));
+
+    //The dispute settles for the buyer, who receives the deposit
+    let mut env = mock_env();
+    env.block.height = ruling.appeal_deadline + 1;
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("any", &[]),
+        ExecuteMsg::ExecuteRuling {},
+    )
+    .unwrap();
+    assert!(res
+        .messages
+        .contains(&SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: trade.buyer.to_string(),
+            amount: vec![Coin::new(deposit.u128(), "uusd")],
+        }))));
+}
+
+#[test]
+fn test_appeal_ruling() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    provide_entropy(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::AssignArbitrator {
+            seed: Some(Binary::from(b"seed".to_vec())),
+        },
+    )
+    .unwrap();
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust("taker", Uint128::new(1_000_000u128)),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReceived,
+        },
+    )
+    .unwrap();
+    let trade = query_trade(&deps);
+
+    //Nothing to appeal before the arbitrator rules
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.buyer.as_str(), &[]),
+        ExecuteMsg::Appeal {},
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::NoRuling {}));
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.arbitrator.as_str(), &[]),
+        ExecuteMsg::Refund {},
+    )
+    .unwrap();
+
+    //Only the buyer lost the ruling, and has to bond the appeal
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust("taker", Uint128::new(5_000_000u128)),
+        ExecuteMsg::Appeal {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::UnauthorizedAppeal { .. }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust(trade.buyer.as_str(), Uint128::new(1_000_000u128)),
+        ExecuteMsg::Appeal {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::AppealBondError { .. }
+    ));
+    let bond = Uint128::new(5_000_000u128);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust(trade.buyer.as_str(), bond),
+        ExecuteMsg::Appeal {},
+    )
+    .unwrap();
+    let appealed = query_trade(&deps);
+    assert_eq!(appealed.state, TradeState::Appealed);
+    assert_eq!(appealed.appellant, Some(trade.buyer.clone()));
+    assert_eq!(appealed.appeal_bond, bond);
+
+    //The ruling is on hold and only governance can settle the trade now
+    let mut env = mock_env();
+    env.block.height = appealed.ruling.unwrap().appeal_deadline + 1;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("any", &[]),
+        ExecuteMsg::ExecuteRuling {},
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::RulingAppealed {}));
+    for sender in [trade.arbitrator.as_str(), "taker"] {
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(sender, &[]),
+            ExecuteMsg::Release {},
+        );
+        assert!(matches!(
+            res.err().unwrap(),
+            TradeError::Unauthorized { .. }
+        ));
+    }
+
+    //Overturning the ruling returns the bond and withholds the arbitration fee
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("gov", &[]),
+        ExecuteMsg::Release {},
+    )
+    .unwrap();
+    assert!(res
+        .messages
+        .contains(&SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: trade.buyer.to_string(),
+            amount: vec![Coin::new(bond.u128(), "uusd")],
+        }))));
+    assert!(!res.messages.iter().any(|msg| matches!(
+        &msg.msg,
+        CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == trade.arbitrator.as_str()
+    )));
+}
+
+#[test]
+fn test_refund_with_other_coins() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    provide_entropy(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::AssignArbitrator {
+            seed: Some(Binary::from(b"seed".to_vec())),
+        },
+    )
+    .unwrap();
+    let deposit = Uint128::new(1_000_000u128);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust("taker", deposit),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReceived,
+        },
+    )
+    .unwrap();
+    let trade = query_trade(&deps);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.arbitrator.as_str(), &[]),
+        ExecuteMsg::Refund {},
+    )
+    .unwrap();
+
+    //Coins of other denoms sent to the contract don't get in the way of the refund
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![
+            Coin::new(1, "uluna"),
+            Coin::new((trade_amount + deposit).u128(), "uusd"),
+        ],
+    );
+    let mut env = mock_env();
+    env.block.height = query_trade(&deps).ruling.unwrap().appeal_deadline + 1;
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("any", &[]),
+        ExecuteMsg::ExecuteRuling {},
+    )
+    .unwrap();
+    let fee = trade_amount.multiply_ratio(1u128, 10u128);
+    let send = |to: &Addr, coin: Coin| {
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.to_string(),
+            amount: vec![coin],
+        }))
+    };
+    assert_eq!(
+        res.messages[..3],
+        [
+            send(
+                &trade.seller,
+                Coin::new((trade_amount + deposit - fee).u128(), "uusd")
+            ),
+            send(&trade.seller, Coin::new(1, "uluna")),
+            send(&trade.arbitrator, Coin::new(fee.u128(), "uusd")),
+        ]
+    );
+}
+
+#[test]
+fn test_fiat_deposited_and_dispute_window() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let mut info = mock_info_with_ust("taker", Uint128::zero());
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), None);
+    let deposit = mock_info_with_ust("taker", Uint128::new(1_000_000u128));
+    let dispute = ExecuteMsg::Dispute {
+        reason: DisputeReason::PaymentNotReceived,
+    };
+
+    //Trades can't be disputed before funding
+    let res = execute(deps.as_mut(), mock_env(), deposit.clone(), dispute.clone());
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidStateChange { .. }
+    ));
+
+    info.funds[0].amount = trade_amount.add(localterra_fee(trade_amount));
+    execute(deps.as_mut(), mock_env(), info.clone(), fund_escrow_msg()).unwrap();
+
+    //Only the buyer can mark the fiat as deposited
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::FiatDeposited {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::UnauthorizedFiatDeposit { .. }
+    ));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offer-owner", &[]),
+        ExecuteMsg::FiatDeposited {},
+    )
+    .unwrap();
+    let trade: TradeData =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    assert_eq!(trade.state, TradeState::FiatDeposited);
+
+    //Once the fiat is deposited the seller can't refund, even after expiration
+    let mut env = mock_env();
+    env.block.height = trade.expire_height + 1;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        ExecuteMsg::Refund {},
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::RefundError { .. }));
+
+    let mut late_env = mock_env();
+    late_env.block.height = trade.expire_height + 14_401;
+    let res = execute(deps.as_mut(), late_env, deposit.clone(), dispute.clone());
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::DisputeWindowClosed { .. }
+    ));
+
+    execute(deps.as_mut(), env, deposit, dispute).unwrap();
+    let trade: TradeData =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::State {}).unwrap()).unwrap();
+    assert_eq!(trade.state, TradeState::Disputed);
+    assert_eq!(
+        trade.dispute_reason,
+        Some(DisputeReason::PaymentNotReceived)
+    );
+}
+
+#[test]
+fn test_multisig_escrow() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let offer = Offer {
+        escrow_mode: Some(EscrowMode::Multisig),
+        ..create_offer_struct(Uint128::new(1_000_000u128), trade_amount, None, None)
+    };
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), Some(offer));
+    provide_entropy(deps.as_mut());
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ExecuteMsg::AssignArbitrator {
+            seed: Some(Binary::from(b"seed".to_vec())),
+        },
+    )
+    .unwrap();
+    let trade = query_trade(&deps);
+    assert_eq!(trade.escrow_mode, EscrowMode::Multisig);
+
+    //A single approval doesn't move the funds
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &[]),
+        ExecuteMsg::Release {},
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &[]),
+        ExecuteMsg::Release {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::AlreadyApproved { .. }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("other", &[]),
+        ExecuteMsg::Refund {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::Unauthorized { .. }
+    ));
+
+    //Approvals of different outcomes don't add up
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.buyer.as_str(), &[]),
+        ExecuteMsg::Refund {},
+    )
+    .unwrap();
+    let approved = query_trade(&deps);
+    assert_eq!(approved.state, TradeState::EscrowFunded);
+    assert_eq!(approved.release_approvals, vec![trade.seller.clone()]);
+    assert_eq!(approved.refund_approvals, vec![trade.buyer.clone()]);
+
+    //The second approval of the release pays out the escrow
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.buyer.as_str(), &[]),
+        ExecuteMsg::Release {},
+    )
+    .unwrap();
+    assert!(!res.messages.is_empty());
+    let released = query_trade(&deps);
+    assert_eq!(released.state, TradeState::Closed);
+    assert!(released.refund_approvals.is_empty());
+}
+
+#[test]
+fn test_multisig_refund_disputed() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let offer = Offer {
+        escrow_mode: Some(EscrowMode::Multisig),
+        ..create_offer_struct(Uint128::new(1_000_000u128), trade_amount, None, None)
+    };
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info.clone(), Some(offer));
+    let trade = query_trade(&deps);
+    let deposit = Uint128::new(1_000_000u128);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info_with_ust(trade.buyer.as_str(), deposit),
+        ExecuteMsg::Dispute {
+            reason: DisputeReason::PaymentNotReleased,
+        },
+    )
+    .unwrap();
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new((trade_amount + deposit).u128(), "uusd")],
+    );
+
+    //Agreeing on a refund without a ruling returns the deposit to the disputer
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.buyer.as_str(), &[]),
+        ExecuteMsg::Refund {},
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(trade.seller.as_str(), &[]),
+        ExecuteMsg::Refund {},
+    )
+    .unwrap();
+    let send = |to: &Addr, amount: Uint128| {
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.to_string(),
+            amount: vec![Coin::new(amount.u128(), "uusd")],
+        }))
+    };
+    assert_eq!(
+        res.messages[..2],
+        [
+            send(&trade.buyer, deposit),
+            send(&trade.seller, trade_amount)
+        ]
+    );
+    assert_eq!(query_trade(&deps).state, TradeState::Canceled);
+}
+
+#[test]
+fn test_release_with_signature() {
+    let trade_amount = Uint128::new(500_000_000u128);
+    let info = mock_info_with_ust("taker", trade_amount);
+    let (_, mut deps) = create_trade(trade_amount, info, None);
+    // Signed by the taker (the seller) over the mock contract address and chain id with nonce 0
+    let signature = Binary::from(hex_decode(
+        "eef67f7593940caa372830997d56a5a50330a394a4de217617ddec6df3a7d1bf\
+         5e7890eefada34ce655fca61bf8d22264c892d3f4a4c88f60aaceaf17ea5b875",
+    ));
+    let signing_key = Binary::from(hex_decode(
+        "03955df0806489f81210511d21e63e2f7ae1e09f7f5e0619adc2ace01f33209a07",
+    ));
+    let release =
+        |nonce: u64, signature: Binary| ExecuteMsg::ReleaseWithSignature { nonce, signature };
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        release(0, signature.clone()),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::SigningKeyNotFound { .. }
+    ));
+
+    deps.querier.with_signing_key(signing_key);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        release(1, signature.clone()),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidNonce { .. }
+    ));
+    let mut forged = signature.to_vec();
+    forged[10] ^= 1;
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        release(0, Binary::from(forged)),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidSignature {}
+    ));
+
+    //Anyone can relay the seller's authorisation, but only once
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        release(0, signature.clone()),
+    )
+    .unwrap();
+    assert!(!res.messages.is_empty());
+    let trade = query_trade(&deps);
+    assert_eq!(trade.state, TradeState::Closed);
+    assert_eq!(trade.release_nonce, 1);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer", &[]),
+        release(0, signature),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidNonce { .. }
+    ));
+}
+
+#[test]
+fn test_htlc_escrow() {
+    let trade_amount = Uint128::new(10_000_000u128);
+    let offer = Offer {
+        escrow_mode: Some(EscrowMode::Htlc),
+        ..create_offer_struct(
+            Uint128::new(1_000_000u128),
+            Uint128::new(500_000_000u128),
+            Some(OfferType::Sell),
+            None,
+        )
+    };
+    let hashlock = Binary::from(Sha256::digest(b"preimage").to_vec());
+    let init_htlc = |hashlock: Option<Binary>| {
+        let mut deps = mock_dependencies(
+            &[Coin::new(trade_amount.u128() * 2, "uusd")],
+            Some(offer.clone()),
+        );
+        let msg = InstantiateMsg {
+            offer_id: 1,
+            arbitrators: arbitrators(),
+            taker_contact: "USTKing".to_string(),
+            seed_commitment: seed_commitment(),
+            hashlock,
+            payment_hash: None,
+            btc_amount: None,
+            payout: None,
+            ust_amount: trade_amount,
+            taker: "taker".to_string(),
+            offers_addr: "offers".to_string(),
+            timestamp: 1641329895,
+        };
+        let res = instantiate(deps.as_mut(), mock_env(), mock_info("taker", &[]), msg);
+        (res, deps)
+    };
+
+    let (res, _) = init_htlc(None);
+    assert!(matches!(res.err().unwrap(), TradeError::InvalidHashlock {}));
+    let (_, mut deps) = init_htlc(Some(hashlock.clone()));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offer-owner", &[]),
+        fund_escrow_msg(),
+    )
+    .unwrap();
+    let trade = query_trade(&deps);
+    let timelock = trade.htlc.unwrap().timelock;
+
+    //Only the hashlock or the timelock settle the trade
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("taker", &[]),
+        ExecuteMsg::FiatDeposited {},
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::UnsupportedEscrowMode { .. }
+    ));
+    let mut env = mock_env();
+    env.block.height = trade.expire_height + 1;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("offer-owner", &[]),
+        ExecuteMsg::Refund {},
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::RefundError { .. }));
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("any", &[]),
+        ExecuteMsg::Claim {
+            preimage: Binary::from(b"guess".to_vec()),
+        },
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::InvalidPreimage {}));
+
+    //Revealing the preimage pays the buyer and publishes it for the counterpart HTLC
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("any", &[]),
+        ExecuteMsg::Claim {
+            preimage: Binary::from(b"preimage".to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(res
+        .messages
+        .iter()
+        .any(|msg| matches!(&msg.msg, CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == "taker")));
+    let trade = query_trade(&deps);
+    assert_eq!(trade.state, TradeState::Closed);
+    assert_eq!(
+        trade.htlc.unwrap().preimage,
+        Some(Binary::from(b"preimage".to_vec()))
+    );
+
+    //From the timelock on the seller gets the escrow back instead
+    let (_, mut deps) = init_htlc(Some(hashlock));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("offer-owner", &[]),
+        fund_escrow_msg(),
+    )
+    .unwrap();
+    let mut env = mock_env();
+    env.block.height = timelock;
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("any", &[]),
+        ExecuteMsg::Claim {
+            preimage: Binary::from(b"preimage".to_vec()),
+        },
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::Expired { .. }));
+    execute(
+        deps.as_mut(),
+        env,
+        mock_info("offer-owner", &[]),
+        ExecuteMsg::Refund {},
+    )
+    .unwrap();
+    assert_eq!(query_trade(&deps).state, TradeState::Canceled);
+}
+
+#[test]
+fn test_settle_lightning() {
+    let trade_amount = Uint128::new(10_000_000u128);
+    let offer = Offer {
+        payment_method: Some(PaymentMethod::Lightning),
+        ..create_offer_struct(
+            Uint128::new(1_000_000u128),
+            Uint128::new(500_000_000u128),
+            None,
+            None,
+        )
+    };
+    let init_lightning = |payment_hash: Option<Binary>| {
+        let mut deps = mock_dependencies(
+            &[Coin::new(trade_amount.u128(), "uusd")],
+            Some(offer.clone()),
+        );
+        let msg = InstantiateMsg {
+            offer_id: 1,
+            arbitrators: arbitrators(),
+            taker_contact: "USTKing".to_string(),
+            seed_commitment: seed_commitment(),
+            hashlock: None,
+            payment_hash,
+            btc_amount: None,
+            payout: None,
+            ust_amount: trade_amount,
+            taker: "taker".to_string(),
+            offers_addr: "offers".to_string(),
+            timestamp: 1641329895,
+        };
+        let info = mock_info_with_ust("taker", trade_amount);
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg);
+        (res, deps)
+    };
+
+    //The seller opens the trade with the payment hash of their invoice
+    let (res, _) = init_lightning(Some(Binary::from(b"not a hash".to_vec())));
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidPaymentHash {}
+    ));
+    let payment_hash = Binary::from(Sha256::digest(b"invoice preimage").to_vec());
+    let (_, mut deps) = init_lightning(Some(payment_hash));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        ExecuteMsg::SettleLightning {
+            preimage: Binary::from(b"guess".to_vec()),
+        },
+    );
+    assert!(matches!(res.err().unwrap(), TradeError::InvalidPreimage {}));
+
+    //Paying the invoice reveals the preimage, which releases the escrow to the buyer
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        ExecuteMsg::SettleLightning {
+            preimage: Binary::from(b"invoice preimage".to_vec()),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.iter().any(|msg| matches!(
+        &msg.msg,
+        CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == "offer-owner"
+    )));
+    assert_eq!(query_trade(&deps).state, TradeState::Closed);
+
+    let (_, mut deps) = create_trade(
+        trade_amount,
+        mock_info_with_ust("taker", trade_amount),
+        None,
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        ExecuteMsg::SettleLightning {
+            preimage: Binary::from(b"invoice preimage".to_vec()),
+        },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::PaymentHashNotSet {}
+    ));
+}
+
+// Regtest payment of 150_000 sats to the seller, mined with two more blocks on top
+const BTC_SELLER_ADDRESS: &str = "bcrt1q5sneatj842n5zldxys68jksprn9sajrsrmww5z";
+const BTC_PAYMENT_TX: &str = "020000000001012514e1475addffb378fdb07e9a1092176c09dbfbd129ebcaacd0099818d2534c0000000000fdffffff02f049020000000000160014a4279eae47aaa7417da62434795a011ccb0ec87068f40c000000000016001412ea12eace7d655f471ce55e34f89b1b77a3d9d0024730303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030302102020202020202020202020202020202020202020202020202020202020202020200000000";
+const BTC_MERKLE_BRANCH: [&str; 2] = [
+    "61ab4183eee2401f1b5b691b02efbd79b3c0129c0a2eaf5fdb3deb9d371e2b78",
+    "c929dc812d345b7e7236904b07312c20fe1708a93c062e2d447f0833ae5909a4",
+];
+const BTC_HEADERS: [&str; 3] = [
+    "0000002073df6226a66ed7d049f1a490c8042dda265d4ed843278f5036062eaa8e6de17cc9f2188ace3bd29002cc20830887d31dbd691b07c080c8928d9cb9eada24159efcb9af5dffff7f2000000000",
+    "0000002094295667f194986e58bcb8d9fd91aac0bb30252fdf636fd6257278f724ebec449a59c5f8229aab55e9f855173ef94485aab8497eea0588f365c871d6d056172254bcaf5dffff7f2004000000",
+    "000000209d67e82c0c0c24c82e1c36053665e9db8cbd7d6dde72bad07e268ca410159a5e6d0b07ee773591f2a1b492d3ca65afdefc90e1cadfcc542a74048bb0ae7daa27acbeaf5dffff7f2002000000",
+];
+// Chained on the first header but easier than the configured minimum difficulty
+const BTC_EASY_HEADER: &str = "0000002094295667f194986e58bcb8d9fd91aac0bb30252fdf636fd6257278f724ebec4497c10efe01d5c9c88704a12d361d8429b3a6aa2412290a0773109d5d2d603d5e54bcaf5dffff002100000000";
+
+fn prove_btc_payment_msg(headers: &[&str]) -> ExecuteMsg {
+    ExecuteMsg::ProveBtcPayment {
+        tx: Binary::from(hex_decode(BTC_PAYMENT_TX)),
+        merkle_branch: BTC_MERKLE_BRANCH
+            .iter()
+            .map(|node| Binary::from(hex_decode(node)))
+            .collect(),
+        tx_index: 1,
+        headers: headers
+            .iter()
+            .map(|header| Binary::from(hex_decode(header)))
+            .collect(),
+    }
+}
+
+#[test]
+fn test_prove_btc_payment() {
+    let trade_amount = Uint128::new(10_000_000u128);
+    let offer = Offer {
+        payment_method: Some(PaymentMethod::Bitcoin),
+        btc_payout_address: Some(BTC_SELLER_ADDRESS.parse().unwrap()),
+        ..create_offer_struct(
+            Uint128::new(1_000_000u128),
+            Uint128::new(500_000_000u128),
+            Some(OfferType::Sell),
+            None,
+        )
+    };
+    let init_btc = |btc_amount: Option<Sats>, confirmed: Option<Sats>, funded_at: u64| {
+        let mut deps = mock_dependencies(
+            &[Coin::new(trade_amount.u128() * 2, "uusd")],
+            Some(offer.clone()),
+        );
+        let msg = InstantiateMsg {
+            offer_id: 1,
+            arbitrators: arbitrators(),
+            taker_contact: "USTKing".to_string(),
+            seed_commitment: seed_commitment(),
+            hashlock: None,
+            payment_hash: None,
+            btc_amount,
+            payout: None,
+            ust_amount: trade_amount,
+            taker: "taker".to_string(),
+            offers_addr: "offers".to_string(),
+            timestamp: 1641329895,
+        };
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(funded_at);
+        let res =
+            instantiate(deps.as_mut(), mock_env(), mock_info("taker", &[]), msg).and_then(|_| {
+                execute(
+                    deps.as_mut(),
+                    env,
+                    mock_info("offer-owner", &[]),
+                    ExecuteMsg::FundEscrow {
+                        entropy: None,
+                        btc_amount: confirmed,
+                    },
+                )
+            });
+        (res, deps)
+    };
+
+    let (res, _) = init_btc(None, None, 0);
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidBtcPayment {}
+    ));
+    let sats = Sats::new(2_100_000_000_000_001);
+    let (res, _) = init_btc(Some(sats), Some(sats), 0);
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InvalidBtcPayment {}
+    ));
+
+    //The buyer picks the sats amount, the seller has to confirm it when funding
+    let (res, _) = init_btc(Some(Sats::new(1)), Some(Sats::new(150_000)), 0);
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::BtcAmountMismatch { sats, .. } if sats == Sats::new(1)
+    ));
+    let (res, _) = init_btc(Some(Sats::new(150_000)), None, 0);
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::BtcAmountMismatch {
+            confirmed: None,
+            ..
+        }
+    ));
+
+    //The proof has to be buried under enough valid blocks
+    let sats = Some(Sats::new(150_000));
+    let (_, mut deps) = init_btc(sats, sats, 0);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&[]),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::NotEnoughConfirmations {
+            required: 3,
+            confirmations: 0
+        }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&BTC_HEADERS[..2]),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::NotEnoughConfirmations {
+            required: 3,
+            confirmations: 2
+        }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&[BTC_HEADERS[0], BTC_HEADERS[2], BTC_HEADERS[1]]),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::BrokenHeaderChain { index: 1 }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&[BTC_HEADERS[0], BTC_EASY_HEADER, BTC_HEADERS[2]]),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::InsufficientWork { index: 1 }
+    ));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        match prove_btc_payment_msg(&BTC_HEADERS) {
+            ExecuteMsg::ProveBtcPayment {
+                tx,
+                merkle_branch,
+                headers,
+                ..
+            } => ExecuteMsg::ProveBtcPayment {
+                tx,
+                merkle_branch,
+                tx_index: 0,
+                headers,
+            },
+            msg => msg,
+        },
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::TransactionNotIncluded {}
+    ));
+
+    //The block has to be buried in the relay's best chain too
+    deps.querier.with_btc_confirmations(2);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&BTC_HEADERS),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::NotEnoughConfirmations {
+            required: 3,
+            confirmations: 2
+        }
+    ));
+    deps.querier.with_btc_confirmations(3);
+
+    //A valid proof releases the escrow to the buyer
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&BTC_HEADERS),
+    )
+    .unwrap();
+    let txid = query_trade(&deps).btc_payment.unwrap().txid.unwrap();
+    assert!(res.attributes.contains(&attr("btc_txid", txid)));
+    assert!(res.messages.iter().any(|msg| matches!(
+        &msg.msg,
+        CosmosMsg::Bank(BankMsg::Send { to_address, .. }) if to_address == "taker"
+    )));
+    assert_eq!(query_trade(&deps).state, TradeState::Closed);
+
+    //The payment has to cover the agreed amount
+    let sats = Some(Sats::new(150_001));
+    let (_, mut deps) = init_btc(sats, sats, 0);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&BTC_HEADERS),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::BtcPaymentNotFound { sats } if sats == Sats::new(150_001)
+    ));
+
+    //and be mined after the escrow was funded
+    let sats = Some(Sats::new(150_000));
+    let (_, mut deps) = init_btc(sats, sats, 86_400);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("any", &[]),
+        prove_btc_payment_msg(&BTC_HEADERS),
+    );
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::StaleBtcPayment { .. }
+    ));
+}
+
+#[test]
+fn test_payout() {
+    let trade_amount = Uint128::new(10_000_000u128);
+    let local_token = AssetInfo::Token {
+        contract_addr: "local-token".to_string(),
+    };
+    let payout = |address: Option<&str>, ask_asset: Option<AssetInfo>| PayoutMsg {
+        address: address.map(|address| address.to_string()),
+        ask_asset,
+        belief_price: Some(Decimal::percent(2)),
+        max_spread: Some(Decimal::percent(1)),
+    };
+    let init_payout = |offer_type: OfferType, payout: PayoutMsg| {
+        let mut deps = mock_dependencies(
+            &[Coin::new(trade_amount.u128() * 2, "uusd")],
+            Some(create_offer_struct(
+                Uint128::new(1_000_000u128),
+                Uint128::new(500_000_000u128),
+                Some(offer_type),
+                None,
+            )),
+        );
+        deps.querier
+            .with_terraswap_pairs(&[(&"uusdlocal-token".to_string(), &"local-ust".to_string())]);
+        let msg = InstantiateMsg {
+            offer_id: 1,
+            arbitrators: arbitrators(),
+            taker_contact: "USTKing".to_string(),
+            seed_commitment: seed_commitment(),
+            hashlock: None,
+            payment_hash: None,
+            btc_amount: None,
+            payout: Some(payout),
+            ust_amount: trade_amount,
+            taker: "taker".to_string(),
+            offers_addr: "offers".to_string(),
+            timestamp: 1641329895,
+        };
+        let res = instantiate(deps.as_mut(), mock_env(), mock_info("taker", &[]), msg);
+        if res.is_ok() {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("offer-owner", &[]),
+                fund_escrow_msg(),
+            )
+            .unwrap();
+        }
+        (res, deps)
+    };
+
+    //Only the buyer can pick the payout, in an asset that has a UST pair
+    let (res, _) = init_payout(OfferType::Buy, payout(Some("wallet"), None));
+    assert!(matches!(res.err().unwrap(), TradeError::InvalidPayout {}));
+    let ust = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    let (res, _) = init_payout(OfferType::Sell, payout(None, Some(ust)));
+    assert!(matches!(res.err().unwrap(), TradeError::InvalidPayout {}));
+    let mir = AssetInfo::Token {
+        contract_addr: "mir-token".to_string(),
+    };
+    let (res, _) = init_payout(OfferType::Sell, payout(None, Some(mir)));
+    assert!(matches!(
+        res.err().unwrap(),
+        TradeError::PayoutPairNotFound { .. }
+    ));
+
+    //UST can go to another address
+    let (_, mut deps) = init_payout(OfferType::Sell, payout(Some("wallet"), None));
+    let res = release_trade(deps.as_mut(), mock_info("offer-owner", &[])).unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "wallet".to_string(),
+            amount: vec![Coin::new(trade_amount.u128(), "uusd")],
+        }))
+    );
+
+    //or be swapped on the pair, to the buyer when no address is given
+    let (_, mut deps) = init_payout(OfferType::Sell, payout(None, Some(local_token.clone())));
+    let swap = query_trade(&deps).payout.unwrap().swap.unwrap();
+    assert_eq!(swap.pair_addr, Addr::unchecked("local-ust"));
+    assert_eq!(swap.ask_asset, local_token);
+    let res = release_trade(deps.as_mut(), mock_info("offer-owner", &[])).unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "local-ust".to_string(),
+                msg: to_binary(&PairExecuteMsg::Swap {
+                    offer_asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: "uusd".to_string(),
+                        },
+                        amount: trade_amount,
+                    },
+                    belief_price: Some(Decimal::percent(2)),
+                    max_spread: Some(Decimal::percent(1)),
+                    to: Some("taker".to_string()),
+                })
+                .unwrap(),
+                funds: vec![Coin::new(trade_amount.u128(), "uusd")],
+            }),
+            gas_limit: None,
+            reply_on: ReplyOn::Error,
+        }
+    );
+
+    //A failed swap falls back to sending the UST
+    let msg = Reply {
+        id: 0,
+        result: ContractResult::Err("Operation exceeds max spread limit".to_string()),
+    };
+    let res = reply(deps.as_mut(), mock_env(), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "taker".to_string(),
+            amount: vec![Coin::new(trade_amount.u128(), "uusd")],
+        }))]
+    );
+}
@@ -0,0 +1,53 @@
+use crate::currencies::FiatCurrency;
+use cosmwasm_std::{Addr, Uint128};
+use cw20::Cw20ReceiveMsg;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+///Messages
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    pub min_stake: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    Receive(Cw20ReceiveMsg),
+    Activate { arbitrator: String },
+    Deactivate { arbitrator: String },
+    Unstake {},
+    Slash { arbitrator: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    Register { fiat_currencies: Vec<FiatCurrency> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    Arbitrator { address: String },
+    ActiveArbitrators { fiat_currency: FiatCurrency },
+}
+
+///Data
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub factory_addr: Addr,
+    pub min_stake: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Arbitrator {
+    pub addr: Addr,
+    pub fiat_currencies: Vec<FiatCurrency>,
+    // Amount of LOCAL bonded by the arbitrator
+    pub stake: Uint128,
+    pub active: bool,
+    // Block height from which a deactivated arbitrator can unstake
+    pub unbonding_height: Option<u64>,
+}
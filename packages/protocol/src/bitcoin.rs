@@ -0,0 +1,652 @@
+use crate::errors::BitcoinError;
+use cosmwasm_std::Uint256;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+
+pub const HEADER_SIZE: usize = 80;
+// Blocks between difficulty adjustments and the time they should take
+pub const RETARGET_INTERVAL: u32 = 2_016;
+pub const TARGET_TIMESPAN: u32 = 14 * 24 * 60 * 60;
+
+/// Double sha256, as used for Bitcoin block hashes, txids and merkle nodes.
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha256::digest(&Sha256::digest(data)));
+    hash
+}
+
+/// Expands a compact `bits` difficulty target into the full 256 bit number.
+pub fn bits_to_target(bits: u32) -> Result<Uint256, BitcoinError> {
+    let exponent = (bits >> 24) as isize;
+    let mantissa = bits & 0x007f_ffff;
+    // Negative and zero targets are invalid
+    if (bits & 0x0080_0000 != 0) | (mantissa == 0) {
+        return Err(BitcoinError::InvalidTarget { bits });
+    }
+
+    let mut target = [0u8; 32];
+    for (i, byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+        // Position of the byte counted from the least significant end
+        let position = exponent - 1 - i as isize;
+        if position > 31 {
+            if *byte != 0 {
+                return Err(BitcoinError::InvalidTarget { bits });
+            }
+        } else if position >= 0 {
+            target[31 - position as usize] = *byte;
+        }
+    }
+    Ok(Uint256::from_be_bytes(target))
+}
+
+/// Compresses a target back into its compact `bits` form, dropping the low order bytes.
+pub fn target_to_bits(target: Uint256) -> u32 {
+    let target = target.to_be_bytes();
+    let size = 32 - target.iter().take_while(|byte| **byte == 0).count();
+    let mut mantissa = [0u8; 4];
+    for i in 0..3 {
+        if size > i {
+            mantissa[1 + i] = target[32 - size + i];
+        }
+    }
+    let mut mantissa = u32::from_be_bytes(mantissa);
+    let mut size = size as u32;
+    // The sign bit can't be set, move the mantissa a byte down instead
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    mantissa | (size << 24)
+}
+
+/// Expected number of hashes to find a block at `target`, as `2^256 / (target + 1)`.
+pub fn target_to_work(target: Uint256) -> Uint256 {
+    // 2^256 doesn't fit, (2^256 - target - 1) / (target + 1) + 1 is the same
+    let one = Uint256::from(1u8);
+    (Uint256::MAX - target) / (target + one) + one
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockHeader {
+    pub version: i32,
+    // Hashes are kept in the internal (little-endian) byte order
+    pub prev_block_hash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+    pub hash: [u8; 32],
+}
+
+impl BlockHeader {
+    pub fn parse(raw: &[u8]) -> Result<BlockHeader, BitcoinError> {
+        if raw.len() != HEADER_SIZE {
+            return Err(BitcoinError::InvalidHeader {});
+        }
+        let mut reader = Reader::new(raw);
+        Ok(BlockHeader {
+            version: reader.u32()? as i32,
+            prev_block_hash: reader.hash()?,
+            merkle_root: reader.hash()?,
+            time: reader.u32()?,
+            bits: reader.u32()?,
+            nonce: reader.u32()?,
+            hash: sha256d(raw),
+        })
+    }
+
+    pub fn target(&self) -> Result<Uint256, BitcoinError> {
+        bits_to_target(self.bits)
+    }
+
+    /// Whether the header hash meets its own target.
+    pub fn check_pow(&self) -> Result<bool, BitcoinError> {
+        Ok(Uint256::from_be_bytes(reversed(&self.hash)) <= self.target()?)
+    }
+
+    /// Block hash in the byte order block explorers display it.
+    pub fn display_hash(&self) -> String {
+        to_display_hash(&self.hash)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxOut {
+    pub value: Sats,
+    pub script_pubkey: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    // Hash of the transaction without witness data, in internal byte order
+    pub txid: [u8; 32],
+    pub outputs: Vec<TxOut>,
+}
+
+impl Transaction {
+    /// Parses a serialized transaction, with or without segwit witness data.
+    pub fn parse(raw: &[u8]) -> Result<Transaction, BitcoinError> {
+        let mut reader = Reader::new(raw);
+        let version = reader.bytes(4)?;
+
+        let segwit = reader.peek()? == 0;
+        if segwit {
+            // Marker and flag
+            if reader.bytes(2)?[1] != 1 {
+                return Err(BitcoinError::InvalidTransaction {});
+            }
+        }
+
+        let body_start = reader.position;
+        let inputs = reader.var_int()?;
+        for _ in 0..inputs {
+            reader.bytes(36)?;
+            let script_len = reader.var_int()?;
+            reader.bytes(script_len as usize)?;
+            reader.bytes(4)?;
+        }
+        let output_count = reader.var_int()?;
+        let mut outputs = vec![];
+        for _ in 0..output_count {
+            let value = Sats::new(reader.u64()?);
+            let script_len = reader.var_int()?;
+            outputs.push(TxOut {
+                value,
+                script_pubkey: reader.bytes(script_len as usize)?.to_vec(),
+            });
+        }
+        let body_end = reader.position;
+
+        if segwit {
+            for _ in 0..inputs {
+                let items = reader.var_int()?;
+                for _ in 0..items {
+                    let item_len = reader.var_int()?;
+                    reader.bytes(item_len as usize)?;
+                }
+            }
+        }
+        let lock_time = reader.bytes(4)?;
+        if (inputs == 0) | (reader.position != raw.len()) {
+            return Err(BitcoinError::InvalidTransaction {});
+        }
+
+        let mut stripped = version.to_vec();
+        stripped.extend_from_slice(&raw[body_start..body_end]);
+        stripped.extend_from_slice(lock_time);
+        Ok(Transaction {
+            txid: sha256d(&stripped),
+            outputs,
+        })
+    }
+}
+
+/// Computes the merkle root from a transaction id and its merkle branch,
+/// `index` is the position of the transaction in the block.
+pub fn merkle_root(txid: &[u8; 32], branch: &[[u8; 32]], index: u32) -> [u8; 32] {
+    let mut hash = *txid;
+    let mut index = index;
+    for node in branch {
+        let mut pair = Vec::with_capacity(64);
+        if index & 1 == 0 {
+            pair.extend_from_slice(&hash);
+            pair.extend_from_slice(node);
+        } else {
+            pair.extend_from_slice(node);
+            pair.extend_from_slice(&hash);
+        }
+        hash = sha256d(&pair);
+        index >>= 1;
+    }
+    hash
+}
+
+pub fn reversed(hash: &[u8; 32]) -> [u8; 32] {
+    let mut reversed = *hash;
+    reversed.reverse();
+    reversed
+}
+
+/// Parses a hash hex encoded in display order into internal byte order.
+pub fn parse_display_hash(hex: &str) -> Result<[u8; 32], BitcoinError> {
+    if (hex.len() != 64) | !hex.is_ascii() {
+        return Err(BitcoinError::InvalidHash {});
+    }
+    let mut hash = [0u8; 32];
+    for (i, byte) in hash.iter_mut().rev().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| BitcoinError::InvalidHash {})?;
+    }
+    Ok(hash)
+}
+
+/// Hex encodes a hash kept in internal byte order the way block explorers display it.
+pub fn to_display_hash(hash: &[u8]) -> String {
+    hash.iter()
+        .rev()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Largest amount of bitcoin that can exist, in satoshis.
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+/// Amount of bitcoin in satoshis.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+pub struct Sats(u64);
+
+impl Sats {
+    pub const fn new(sats: u64) -> Sats {
+        Sats(sats)
+    }
+
+    pub fn u64(&self) -> u64 {
+        self.0
+    }
+
+    /// Whether the amount is positive and not above the bitcoin supply.
+    pub fn is_valid_amount(&self) -> bool {
+        (self.0 > 0) & (self.0 <= MAX_MONEY)
+    }
+}
+
+impl fmt::Display for Sats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} sats", self.0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BtcNetwork {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl BtcNetwork {
+    fn bech32_hrp(&self) -> &'static str {
+        match self {
+            BtcNetwork::Mainnet => "bc",
+            BtcNetwork::Testnet => "tb",
+            BtcNetwork::Regtest => "bcrt",
+        }
+    }
+
+    fn p2pkh_version(&self) -> u8 {
+        match self {
+            BtcNetwork::Mainnet => 0x00,
+            _ => 0x6f,
+        }
+    }
+
+    fn p2sh_version(&self) -> u8 {
+        match self {
+            BtcNetwork::Mainnet => 0x05,
+            _ => 0xc4,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BtcPayload {
+    P2pkh([u8; 20]),
+    P2sh([u8; 20]),
+    P2wpkh([u8; 20]),
+    P2wsh([u8; 32]),
+    P2tr([u8; 32]),
+}
+
+/// Bitcoin address, (de)serialized as its usual string form.
+///
+/// Testnet and regtest share their base58 prefixes, those addresses parse as `Testnet` and
+/// are accepted on both networks by `require_network`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BtcAddress {
+    pub network: BtcNetwork,
+    pub payload: BtcPayload,
+}
+
+impl BtcAddress {
+    pub fn require_network(&self, network: BtcNetwork) -> Result<(), BitcoinError> {
+        let base58 = matches!(self.payload, BtcPayload::P2pkh(_) | BtcPayload::P2sh(_));
+        let shared_prefix =
+            base58 & (self.network == BtcNetwork::Testnet) & (network == BtcNetwork::Regtest);
+        if (self.network != network) & !shared_prefix {
+            return Err(BitcoinError::WrongNetwork {
+                network: self.network,
+                expected: network,
+            });
+        }
+        Ok(())
+    }
+
+    /// Output script paying to the address.
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        match &self.payload {
+            // OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG
+            BtcPayload::P2pkh(hash) => [&[0x76, 0xa9, 0x14][..], hash, &[0x88, 0xac]].concat(),
+            // OP_HASH160 <hash> OP_EQUAL
+            BtcPayload::P2sh(hash) => [&[0xa9, 0x14][..], hash, &[0x87]].concat(),
+            // <witness version> <program>
+            BtcPayload::P2wpkh(hash) => [&[0x00, 0x14][..], hash].concat(),
+            BtcPayload::P2wsh(hash) => [&[0x00, 0x20][..], hash].concat(),
+            BtcPayload::P2tr(key) => [&[0x51, 0x20][..], key].concat(),
+        }
+    }
+}
+
+impl FromStr for BtcAddress {
+    type Err = BitcoinError;
+
+    fn from_str(address: &str) -> Result<BtcAddress, BitcoinError> {
+        let lowercase = address.to_lowercase();
+        let network = [
+            BtcNetwork::Mainnet,
+            BtcNetwork::Testnet,
+            BtcNetwork::Regtest,
+        ]
+        .iter()
+        .copied()
+        // "bcrt1" also starts with "bc", so the separator is part of the prefix
+        .find(|network| lowercase.starts_with(&format!("{}1", network.bech32_hrp())));
+        match network {
+            Some(network) => parse_segwit_address(address, network),
+            None => parse_base58_address(address),
+        }
+    }
+}
+
+impl fmt::Display for BtcAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let address = match &self.payload {
+            BtcPayload::P2pkh(hash) => base58check_encode(self.network.p2pkh_version(), hash),
+            BtcPayload::P2sh(hash) => base58check_encode(self.network.p2sh_version(), hash),
+            BtcPayload::P2wpkh(hash) => bech32_encode(self.network.bech32_hrp(), 0, hash),
+            BtcPayload::P2wsh(hash) => bech32_encode(self.network.bech32_hrp(), 0, hash),
+            BtcPayload::P2tr(key) => bech32_encode(self.network.bech32_hrp(), 1, key),
+        };
+        write!(f, "{}", address)
+    }
+}
+
+impl Serialize for BtcAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for BtcAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BtcAddress, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        address.parse().map_err(de::Error::custom)
+    }
+}
+
+impl JsonSchema for BtcAddress {
+    fn schema_name() -> String {
+        "BtcAddress".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+fn parse_base58_address(address: &str) -> Result<BtcAddress, BitcoinError> {
+    // Version byte, 20 byte hash and 4 byte checksum
+    let data = base58_decode(address)?;
+    if data.len() != 25 {
+        return Err(BitcoinError::InvalidAddress {});
+    }
+    let (payload, checksum) = data.split_at(21);
+    if sha256d(payload)[..4] != *checksum {
+        return Err(BitcoinError::InvalidChecksum {});
+    }
+    let hash = payload[1..].try_into().unwrap();
+    let (network, payload) = match payload[0] {
+        0x00 => (BtcNetwork::Mainnet, BtcPayload::P2pkh(hash)),
+        0x05 => (BtcNetwork::Mainnet, BtcPayload::P2sh(hash)),
+        0x6f => (BtcNetwork::Testnet, BtcPayload::P2pkh(hash)),
+        0xc4 => (BtcNetwork::Testnet, BtcPayload::P2sh(hash)),
+        _ => return Err(BitcoinError::InvalidAddress {}),
+    };
+    Ok(BtcAddress { network, payload })
+}
+
+fn base58_decode(encoded: &str) -> Result<Vec<u8>, BitcoinError> {
+    // Anything longer can't decode to an address
+    if encoded.len() > 35 {
+        return Err(BitcoinError::InvalidAddress {});
+    }
+    let mut bytes: Vec<u8> = vec![];
+    for c in encoded.bytes() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or(BitcoinError::InvalidAddress {})? as u32;
+        for byte in bytes.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.insert(0, carry as u8);
+            carry >>= 8;
+        }
+    }
+    // Leading ones stand for leading zero bytes
+    let zeros = encoded.bytes().take_while(|c| *c == b'1').count();
+    Ok([vec![0u8; zeros], bytes].concat())
+}
+
+fn base58check_encode(version: u8, hash: &[u8]) -> String {
+    let mut data = vec![version];
+    data.extend_from_slice(hash);
+    let checksum = sha256d(&data);
+    data.extend_from_slice(&checksum[..4]);
+
+    let mut digits: Vec<u8> = vec![];
+    for byte in &data {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut().rev() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.insert(0, (carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = data.iter().take_while(|byte| **byte == 0).count();
+    std::iter::repeat(b'1')
+        .take(zeros)
+        .chain(digits.iter().map(|digit| BASE58_ALPHABET[*digit as usize]))
+        .map(char::from)
+        .collect()
+}
+
+fn parse_segwit_address(address: &str, network: BtcNetwork) -> Result<BtcAddress, BitcoinError> {
+    if (address.len() > 90)
+        | !address.is_ascii()
+        | ((address.to_lowercase() != address) & (address.to_uppercase() != address))
+    {
+        return Err(BitcoinError::InvalidAddress {});
+    }
+    let hrp = network.bech32_hrp();
+    let data = address.to_lowercase()[hrp.len() + 1..]
+        .bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|a| *a == c).map(|i| i as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(BitcoinError::InvalidAddress {})?;
+    // Witness version and 6 checksum characters at least
+    if data.len() < 7 {
+        return Err(BitcoinError::InvalidAddress {});
+    }
+
+    let checksum = bech32_polymod(&[bech32_hrp_expand(hrp), data.clone()].concat());
+    let version = data[0];
+    // Version 0 uses bech32, later versions bech32m
+    let expected_checksum = if version == 0 {
+        BECH32_CONST
+    } else {
+        BECH32M_CONST
+    };
+    if checksum != expected_checksum {
+        return Err(BitcoinError::InvalidChecksum {});
+    }
+
+    let program = convert_bits(&data[1..data.len() - 6], 5, 8, false)
+        .ok_or(BitcoinError::InvalidAddress {})?;
+    let payload = match (version, program.len()) {
+        (0, 20) => BtcPayload::P2wpkh(program.as_slice().try_into().unwrap()),
+        (0, 32) => BtcPayload::P2wsh(program.as_slice().try_into().unwrap()),
+        (1, 32) => BtcPayload::P2tr(program.as_slice().try_into().unwrap()),
+        _ => return Err(BitcoinError::InvalidAddress {}),
+    };
+    Ok(BtcAddress { network, payload })
+}
+
+fn bech32_encode(hrp: &str, version: u8, program: &[u8]) -> String {
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).unwrap_or_default());
+    let constant = if version == 0 {
+        BECH32_CONST
+    } else {
+        BECH32M_CONST
+    };
+    let polymod =
+        bech32_polymod(&[bech32_hrp_expand(hrp), data.clone(), vec![0; 6]].concat()) ^ constant;
+    data.extend((0..6).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8));
+    let data: String = data
+        .iter()
+        .map(|d| char::from(BECH32_CHARSET[*d as usize]))
+        .collect();
+    format!("{}1{}", hrp, data)
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x01ff_ffff) << 5) ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn bech32_hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|c| c & 31));
+    expanded
+}
+
+// Regroups `from` bit values into `to` bit values, None on invalid padding
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0u32;
+    let mut result = vec![];
+    let max = (1u32 << to) - 1;
+    let max_acc = (1u32 << (from + to - 1)) - 1;
+    for value in data {
+        acc = ((acc << from) | *value as u32) & max_acc;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & max) as u8);
+        }
+    } else if (bits >= from) | ((acc << (to - bits)) & max != 0) {
+        return None;
+    }
+    Some(result)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+
+    fn peek(&self) -> Result<u8, BitcoinError> {
+        self.data
+            .get(self.position)
+            .copied()
+            .ok_or(BitcoinError::UnexpectedEnd {})
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], BitcoinError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(BitcoinError::UnexpectedEnd {})?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, BitcoinError> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, BitcoinError> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn hash(&mut self) -> Result<[u8; 32], BitcoinError> {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(self.bytes(32)?);
+        Ok(hash)
+    }
+
+    fn var_int(&mut self) -> Result<u64, BitcoinError> {
+        let value = match self.bytes(1)?[0] {
+            0xfd => {
+                let mut buf = [0u8; 2];
+                buf.copy_from_slice(self.bytes(2)?);
+                u16::from_le_bytes(buf) as u64
+            }
+            0xfe => self.u32()? as u64,
+            0xff => self.u64()?,
+            value => value as u64,
+        };
+        // Anything longer than the remaining data is malformed
+        if value > self.data.len() as u64 {
+            return Err(BitcoinError::UnexpectedEnd {});
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,61 @@
+use cosmwasm_std::{Addr, Binary, Uint256};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+///Messages
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    // Raw header the relay starts from, it has to open a retarget period
+    pub checkpoint: Binary,
+    pub checkpoint_height: u32,
+    // Compact target of the easiest difficulty allowed
+    pub pow_limit: u32,
+    // Regtest keeps the same difficulty forever
+    pub no_retargeting: bool,
+    // Testnet accepts blocks at `pow_limit` once 20 minutes passed since the previous one
+    pub min_difficulty_blocks: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    SubmitHeaders { headers: Vec<Binary> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    Tip {},
+    Header {
+        block_hash: String,
+    },
+    /// Number of blocks on top of and including `block_hash` in the best chain, zero when it
+    /// isn't part of it. `block_hash` is hex encoded in the order block explorers display it.
+    Confirmations {
+        block_hash: String,
+    },
+}
+
+///Data
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub factory_addr: Addr,
+    pub pow_limit: u32,
+    pub no_retargeting: bool,
+    pub min_difficulty_blocks: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StoredHeader {
+    // Hashes are in internal byte order
+    pub hash: Binary,
+    pub prev_block_hash: Binary,
+    pub height: u32,
+    pub time: u32,
+    pub bits: u32,
+    // Time of the first block of the retarget period the header is in
+    pub period_start_time: u32,
+    // Total work of the chain up to and including this header, from the checkpoint
+    pub chain_work: Uint256,
+}
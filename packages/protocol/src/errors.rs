@@ -1,5 +1,8 @@
-use crate::offer::OfferState;
-use cosmwasm_std::{Addr, StdError};
+use crate::bitcoin::BtcNetwork;
+use crate::currencies::FiatCurrency;
+use crate::offer::{OfferState, OfferType, PaymentMethod};
+use crate::trade::{EscrowMode, TradeState};
+use cosmwasm_std::{Addr, Decimal, StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,4 +17,95 @@ pub enum OfferError {
     GovernanceNotFound { gov_addr: Addr },
     #[error("Invalid reply message id.")]
     InvalidReply {},
+    #[error("Offer has open trades.")]
+    OpenTrades { offer_id: u64, open_trades: u64 },
+    #[error("Offer has trades, archive it instead.")]
+    HasTrades { offer_id: u64 },
+    #[error("Offer is expired or its maker is away.")]
+    OfferUnavailable { offer_id: u64 },
+    #[error("Offer not found.")]
+    OfferNotFound { offer_id: u64 },
+    #[error("Offer is not active.")]
+    OfferNotActive { offer_id: u64, state: OfferState },
+    #[error("Makers can't take their own offers.")]
+    TakerIsMaker { taker: Addr },
+    #[error("Amount is outside of offer amount range.")]
+    AmountError {
+        amount: Uint128,
+        min_amount: Uint128,
+        max_amount: Uint128,
+    },
+    #[error("No arbitrator available for this currency.")]
+    NoArbitratorAvailable { fiat_currency: FiatCurrency },
+    #[error("Offer has reached its maximum number of open trades.")]
+    MaxOpenTrades { offer_id: u64, max_open_trades: u32 },
+    #[error("Taker must wait before opening another trade on this offer.")]
+    TakerCooldown { offer_id: u64, available_at: u64 },
+    #[error("Trade not found.")]
+    TradeNotFound { trade: Addr },
+    #[error("Bitcoin payment already released another trade.")]
+    BtcPaymentReused { txid: String, trade: Addr },
+    #[error("Taker is not on the offer allowlist.")]
+    TakerNotAllowed { offer_id: u64, taker: Addr },
+    #[error("Taker is blocked on this offer.")]
+    TakerBlocked { offer_id: u64, taker: Addr },
+    #[error("Taker has not completed enough trades for this offer.")]
+    NotEnoughCompletedTrades {
+        offer_id: u64,
+        completed_trades: u64,
+        min_completed_trades: u64,
+    },
+    #[error("Taker dispute rate is above the offer maximum.")]
+    DisputeRateTooHigh {
+        offer_id: u64,
+        dispute_rate: Decimal,
+        max_dispute_rate: Decimal,
+    },
+    #[error("Only closed or settled trades can be rated.")]
+    TradeNotRateable { trade: Addr, state: TradeState },
+    #[error("Only the buyer and the seller can rate a trade.")]
+    NotTradeParty { trade: Addr, caller: Addr },
+    #[error("Trade was already rated.")]
+    AlreadyRated { trade: Addr, rater: Addr },
+    #[error("Rating must be between 1 and 5.")]
+    InvalidRating { rating: u8 },
+    #[error("Comment hash is too long.")]
+    CommentHashTooLong { max_length: usize },
+    #[error("Address has no registered encryption key.")]
+    EncryptionKeyNotFound { addr: Addr },
+    #[error("Escrow mode isn't available for this offer type.")]
+    InvalidEscrowMode {
+        offer_type: OfferType,
+        escrow_mode: EscrowMode,
+    },
+    #[error("Payment method isn't available for this offer.")]
+    InvalidPaymentMethod {
+        offer_type: OfferType,
+        payment_method: PaymentMethod,
+    },
+    #[error("{0}")]
+    Bitcoin(#[from] BitcoinError),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BitcoinError {
+    #[error("Unexpected end of data.")]
+    UnexpectedEnd {},
+    #[error("Block header must be 80 bytes.")]
+    InvalidHeader {},
+    #[error("Malformed transaction.")]
+    InvalidTransaction {},
+    #[error("Invalid difficulty target.")]
+    InvalidTarget { bits: u32 },
+    #[error("Block hash must be 32 hex encoded bytes.")]
+    InvalidHash {},
+    #[error("Invalid Bitcoin address.")]
+    InvalidAddress {},
+    #[error("Invalid Bitcoin address checksum.")]
+    InvalidChecksum {},
+    #[error("Bitcoin address is for another network.")]
+    WrongNetwork {
+        network: BtcNetwork,
+        expected: BtcNetwork,
+    },
 }
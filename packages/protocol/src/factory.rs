@@ -1,4 +1,5 @@
-use cosmwasm_std::{Addr, Uint128};
+use crate::bitcoin::BtcNetwork;
+use cosmwasm_std::{Addr, Binary, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -11,8 +12,26 @@ pub struct InstantiateMsg {
     pub trading_incentives_code_id: u64,
     pub offer_code_id: u64,
     pub trade_code_id: u64,
+    pub arbitrator_registry_code_id: u64,
+    pub btc_relay_code_id: u64,
     pub fee_collector_threshold: Uint128,
+    pub arbitrator_min_stake: Uint128,
+    // UST amount a trader has to deposit to open a dispute
+    pub dispute_deposit: Uint128,
+    // UST amount the losing party has to bond to appeal a ruling
+    pub appeal_bond: Uint128,
+    // Compact target of the easiest difficulty accepted in Bitcoin payment proofs
+    pub btc_pow_limit: u32,
+    // Number of block headers a Bitcoin payment proof has to include, at least one
+    pub btc_confirmations: u32,
+    // Network BTC payout addresses must belong to, regtest also disables retargeting in the relay
+    pub btc_network: BtcNetwork,
+    // Raw Bitcoin header the relay starts from, at the start of a retarget period
+    pub btc_checkpoint: Binary,
+    pub btc_checkpoint_height: u32,
     pub local_ust_pool_addr: String,
+    // Used to find the pair buyers swap their payout on
+    pub terraswap_factory_addr: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -30,8 +49,16 @@ pub struct Config {
     pub trade_code_id: u64,
     pub token_addr: Addr,
     pub local_ust_pool_addr: Addr,
+    pub terraswap_factory_addr: Addr,
     pub gov_addr: Addr,
     pub offers_addr: Addr,
     pub fee_collector_addr: Addr,
     pub trading_incentives_addr: Addr,
+    pub arbitrator_registry_addr: Addr,
+    pub btc_relay_addr: Addr,
+    pub dispute_deposit: Uint128,
+    pub appeal_bond: Uint128,
+    pub btc_pow_limit: u32,
+    pub btc_confirmations: u32,
+    pub btc_network: BtcNetwork,
 }
@@ -12,6 +12,9 @@ pub struct InstantiateMsg {}
 pub enum ExecuteMsg {
     Receive(Cw20ReceiveMsg),
     Withdraw { shares: Uint128 },
+    OpenDispute { trade: String },
+    VoteDispute { trade: String, release: bool },
+    ResolveDispute { trade: String },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -27,6 +30,7 @@ pub enum QueryMsg {
     Config {},
     State {},
     Staker { address: String },
+    Dispute { trade: String },
 }
 
 ///Data
@@ -46,3 +50,13 @@ pub struct Staker {
     pub address: String,
     pub shares: Uint128,
 }
+
+/// Dispute escalated to a vote of the LOCAL stakers, votes are weighted by shares.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Dispute {
+    pub trade: Addr,
+    pub release_votes: Uint128,
+    pub refund_votes: Uint128,
+    pub end_height: u64,
+    pub resolved: bool,
+}
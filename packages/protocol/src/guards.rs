@@ -18,3 +18,12 @@ pub fn assert_min_g_max(min: Uint128, max: Uint128) -> Result<(), OfferError> {
         Ok(())
     }
 }
+
+pub fn assert_expiration(expires_at: Option<u64>, now: u64) -> Result<(), OfferError> {
+    match expires_at {
+        Some(expires_at) if expires_at <= now => Err(OfferError::Std(StdError::generic_err(
+            "Expiration must be in the future.",
+        ))),
+        _ => Ok(()),
+    }
+}
@@ -1,3 +1,6 @@
+pub mod arbitrator_registry;
+pub mod bitcoin;
+pub mod btc_relay;
 pub mod constants;
 pub mod currencies;
 pub mod errors;
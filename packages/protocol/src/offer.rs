@@ -1,9 +1,10 @@
 use super::constants::OFFERS_KEY;
+use crate::bitcoin::{BtcAddress, Sats};
 use crate::currencies::FiatCurrency;
 use crate::errors::OfferError;
-use crate::trade::{TradeData, TradeState};
-use cosmwasm_std::{Addr, Deps, Order, StdResult, Storage, Uint128};
-use cw_storage_plus::{Bound, Index, IndexList, IndexedMap, MultiIndex};
+use crate::trade::{DisputeReason, EscrowMode, PayoutMsg, TradeData, TradeState};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Deps, Env, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::{Bound, Index, IndexList, IndexedMap, Map, MultiIndex};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self};
@@ -60,6 +61,9 @@ pub fn offers<'a>() -> IndexedMap<'a, &'a str, Offer, OfferIndexes<'a>> {
 
 // pub const OFFERS : IndexedMap<&str, Offer, OfferIndexes> = create_offers_indexedmap();
 
+// Makers that are away until the stored timestamp (in seconds)
+pub const MAKERS_AWAY: Map<&Addr, u64> = Map::new("makers_away");
+
 ///Messages
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {}
@@ -70,7 +74,16 @@ pub struct OfferMsg {
     pub fiat_currency: FiatCurrency,
     pub min_amount: Uint128,
     pub max_amount: Uint128,
+    // Public handle shown with the offer, the actual contact is sent encrypted on each trade
     pub maker_contact: String,
+    pub expires_at: Option<u64>,
+    pub max_open_trades: Option<u32>,
+    pub taker_cooldown: Option<u64>,
+    pub taker_requirements: Option<TakerRequirements>,
+    pub escrow_mode: Option<EscrowMode>,
+    pub payment_method: Option<PaymentMethod>,
+    // Address the maker wants to be paid to, required by Bitcoin offers
+    pub btc_payout_address: Option<BtcAddress>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -89,13 +102,56 @@ pub enum ExecuteMsg {
         id: u64,
         offer: OfferMsg,
     },
+    Archive {
+        id: u64,
+    },
+    Delete {
+        id: u64,
+    },
+    SetAway {
+        until: u64,
+    },
+    Batch {
+        msgs: Vec<BatchMsg>,
+    },
     NewTrade {
         offer_id: u64,
-        ust_amount: String,
+        ust_amount: Uint128,
         taker: String, // TODO should be Addr
+        // Encrypted with the maker's profile encryption key
         taker_contact: String,
-        arbitrator: String, // TODO should be Addr
+        // sha256 of a secret seed, revealed after funding to pick the arbitrator
+        seed_commitment: Binary,
+        hashlock: Option<Binary>,
+        // Payment hash of the seller's invoice, required by Lightning offers
+        payment_hash: Option<Binary>,
+        // Amount the buyer pays, required by Bitcoin offers
+        btc_amount: Option<Sats>,
+        // Where the buyer wants the escrow paid out, for Sell offers where the taker buys
+        payout: Option<PayoutMsg>,
     },
+    UpdateTradeState {},
+    UpdateProfile {
+        encryption_key: Binary,
+        metadata: Option<String>,
+        // secp256k1 public key for off-chain signed releases
+        signing_key: Option<Binary>,
+    },
+    RateTrade {
+        trade: String,
+        rating: u8,
+        comment_hash: Option<String>,
+    },
+}
+
+/// Maker operations that can be sent together in a single `ExecuteMsg::Batch`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMsg {
+    Pause { id: u64 },
+    Activate { id: u64 },
+    Update { id: u64, offer: OfferMsg },
+    Archive { id: u64 },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -136,9 +192,37 @@ pub enum QueryMsg {
         last_value: Option<u64>,
         limit: u32,
     },
+    OffersQueryWithStats {
+        owner: Option<Addr>,
+        last_value: Option<u64>,
+        limit: u32,
+    },
+    OffersWithStats {
+        offer_type: OfferType,
+        fiat_currency: FiatCurrency,
+        last_value: Option<u64>,
+        limit: u32,
+    },
     Offer {
         id: u64,
     },
+    TraderStats {
+        address: Addr,
+    },
+    Rating {
+        trade: Addr,
+        rater: Addr,
+    },
+    Profile {
+        address: Addr,
+    },
+    ArbitratorStats {
+        arbitrator: Addr,
+    },
+    ArbitratorQueue {
+        arbitrator: Addr,
+        order: QueueOrder,
+    },
     TradesQuery {
         user: Addr,
         state: Option<TradeState>,
@@ -170,6 +254,113 @@ pub struct Offer {
     pub max_amount: Uint128,
     pub state: OfferState,
     pub timestamp: u64,
+    pub expires_at: Option<u64>,
+    pub max_open_trades: Option<u32>,
+    pub taker_cooldown: Option<u64>,
+    pub taker_requirements: Option<TakerRequirements>,
+    pub escrow_mode: Option<EscrowMode>,
+    pub payment_method: Option<PaymentMethod>,
+    pub btc_payout_address: Option<BtcAddress>,
+}
+
+/// Conditions a taker has to meet to open a trade on an offer, unset fields aren't checked.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TakerRequirements {
+    pub min_completed_trades: Option<u64>,
+    pub max_dispute_rate: Option<Decimal>,
+    pub allowlist: Option<Vec<Addr>>,
+    pub blocklist: Option<Vec<Addr>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOrder {
+    OldestFirst,
+    NewestFirst,
+}
+
+/// Performance record of an arbitrator across the trades assigned to them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct ArbitratorStats {
+    pub resolved_disputes: u64,
+    // Average time (in seconds) from the dispute to its settlement
+    pub avg_resolution_time: u64,
+    pub settled_for_maker: u64,
+    pub settled_for_taker: u64,
+    // Disputes taken away from the arbitrator after the dispute deadline passed
+    pub missed_deadlines: u64,
+}
+
+impl ArbitratorStats {
+    pub fn add_resolution(&mut self, state: &TradeState, resolution_time: u64) {
+        let total = self.avg_resolution_time as u128 * self.resolved_disputes as u128;
+        self.resolved_disputes += 1;
+        self.avg_resolution_time =
+            ((total + resolution_time as u128) / self.resolved_disputes as u128) as u64;
+        if state == &TradeState::SettledForMaker {
+            self.settled_for_maker += 1;
+        } else {
+            self.settled_for_taker += 1;
+        }
+    }
+}
+
+/// Counters kept for every address that took part in a trade, updated when the trade is finished.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct TraderStats {
+    pub completed_trades: u64,
+    pub canceled_trades: u64,
+    // Completed trades that went through a dispute
+    pub disputed_trades: u64,
+    pub disputes_opened: u64,
+    pub disputes_won: u64,
+    pub disputes_lost: u64,
+    pub volume: Vec<Coin>,
+    // Trades released by this address as seller and their average time from funding to release
+    pub released_trades: u64,
+    pub avg_release_time: u64,
+    // Ratings received from counterparties
+    pub ratings_count: u64,
+    pub ratings_total: u64,
+}
+
+impl TraderStats {
+    pub fn dispute_rate(&self) -> Decimal {
+        if self.completed_trades == 0 {
+            Decimal::zero()
+        } else {
+            Decimal::from_ratio(self.disputed_trades, self.completed_trades)
+        }
+    }
+
+    /// Average rating received, from 1 to 5, or zero if the trader has never been rated.
+    pub fn rating_score(&self) -> Decimal {
+        if self.ratings_count == 0 {
+            Decimal::zero()
+        } else {
+            Decimal::from_ratio(self.ratings_total, self.ratings_count)
+        }
+    }
+
+    pub fn add_volume(&mut self, coin: Coin) {
+        match self.volume.iter_mut().find(|c| c.denom == coin.denom) {
+            Some(c) => c.amount += coin.amount,
+            None => self.volume.push(coin),
+        }
+    }
+
+    pub fn add_release_time(&mut self, release_time: u64) {
+        let total = self.avg_release_time as u128 * self.released_trades as u128;
+        self.released_trades += 1;
+        self.avg_release_time =
+            ((total + release_time as u128) / self.released_trades as u128) as u64;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OfferWithStats {
+    pub offer: Offer,
+    pub maker_stats: TraderStats,
 }
 
 pub struct OfferModel<'a> {
@@ -214,7 +405,7 @@ impl OfferModel<'_> {
                 OfferModel::store(self.storage, &self.offer).unwrap();
                 Ok(&self.offer)
             }
-            OfferState::Active => Err(OfferError::InvalidStateChange {
+            OfferState::Active | OfferState::Archived => Err(OfferError::InvalidStateChange {
                 from: self.offer.state.clone(),
                 to: OfferState::Active,
             }),
@@ -228,32 +419,86 @@ impl OfferModel<'_> {
                 OfferModel::store(self.storage, &self.offer).unwrap();
                 Ok(&self.offer)
             }
-            OfferState::Paused => Err(OfferError::InvalidStateChange {
+            OfferState::Paused | OfferState::Archived => Err(OfferError::InvalidStateChange {
                 from: self.offer.state.clone(),
                 to: OfferState::Paused,
             }),
         }
     }
 
+    pub fn archive(&mut self) -> Result<&Offer, OfferError> {
+        match self.offer.state {
+            OfferState::Active | OfferState::Paused => {
+                self.offer.state = OfferState::Archived;
+                OfferModel::store(self.storage, &self.offer).unwrap();
+                Ok(&self.offer)
+            }
+            OfferState::Archived => Err(OfferError::InvalidStateChange {
+                from: self.offer.state.clone(),
+                to: OfferState::Archived,
+            }),
+        }
+    }
+
+    /// Removes the offer from the store and, through the `IndexedMap`, from every `OfferIndexes`.
+    pub fn delete(self) -> StdResult<Offer> {
+        offers().remove(self.storage, &self.offer.id.to_string())?;
+        Ok(self.offer)
+    }
+
     pub fn update(&mut self, msg: OfferMsg) -> &Offer {
         self.offer.offer_type = msg.offer_type;
         self.offer.fiat_currency = msg.fiat_currency;
         self.offer.min_amount = msg.min_amount;
         self.offer.max_amount = msg.max_amount;
+        self.offer.expires_at = msg.expires_at;
+        self.offer.max_open_trades = msg.max_open_trades;
+        self.offer.taker_cooldown = msg.taker_cooldown;
+        self.offer.taker_requirements = msg.taker_requirements;
+        self.offer.escrow_mode = msg.escrow_mode;
+        self.offer.payment_method = msg.payment_method;
+        self.offer.btc_payout_address = msg.btc_payout_address;
         OfferModel::store(self.storage, &self.offer).unwrap();
         &self.offer
         // self.save()
         //     ^^^^ move occurs because `*self` has type `OfferModel<'_>`, which does not implement the `Copy` trait
     }
 
+    /// Active offers that expired or whose maker is away are treated as paused.
+    pub fn effective_state(storage: &dyn Storage, offer: &Offer, now: u64) -> OfferState {
+        let expired = offer
+            .expires_at
+            .map_or(false, |expires_at| now >= expires_at);
+        let away = MAKERS_AWAY
+            .may_load(storage, &offer.owner)
+            .unwrap_or_default()
+            .map_or(false, |until| now < until);
+
+        if (offer.state == OfferState::Active) & (expired | away) {
+            OfferState::Paused
+        } else {
+            offer.state.clone()
+        }
+    }
+
+    fn with_effective_state(storage: &dyn Storage, mut offer: Offer, now: u64) -> Offer {
+        offer.state = OfferModel::effective_state(storage, &offer, now);
+        offer
+    }
+
     pub fn query_all_offers(
         storage: &dyn Storage,
+        env: Env,
         fiat_currency: FiatCurrency,
     ) -> StdResult<Vec<Offer>> {
+        let now = env.block.time.seconds();
+
         let result: Vec<Offer> = offers()
             .range(storage, None, None, Order::Ascending)
             .flat_map(|item| item.and_then(|(_, offer)| Ok(offer)))
             .filter(|offer| offer.fiat_currency == fiat_currency)
+            .filter(|offer| offer.state != OfferState::Archived)
+            .map(|offer| OfferModel::with_effective_state(storage, offer, now))
             .collect();
 
         Ok(result)
@@ -261,11 +506,13 @@ impl OfferModel<'_> {
 
     pub fn query_by_type(
         deps: Deps,
+        env: Env,
         offer_type: OfferType,
         last_value: Option<u64>,
         limit: u32,
     ) -> StdResult<Vec<Offer>> {
         let storage = deps.storage;
+        let now = env.block.time.seconds();
 
         let range_from = match last_value {
             Some(thing) => Some(Bound::Exclusive(Vec::from(thing.to_string()))),
@@ -277,8 +524,10 @@ impl OfferModel<'_> {
             .offer_type
             .prefix(offer_type.to_string())
             .range(storage, range_from, None, Order::Ascending)
-            .take(limit as usize)
             .flat_map(|item| item.and_then(|(_, offer)| Ok(offer)))
+            .filter(|offer| offer.state != OfferState::Archived)
+            .map(|offer| OfferModel::with_effective_state(storage, offer, now))
+            .take(limit as usize)
             .collect();
 
         Ok(result)
@@ -286,12 +535,14 @@ impl OfferModel<'_> {
 
     pub fn query_by_type_fiat(
         deps: Deps,
+        env: Env,
         offer_type: OfferType,
         fiat_currency: FiatCurrency,
         last_value: Option<u64>,
         limit: u32,
     ) -> StdResult<Vec<Offer>> {
         let storage = deps.storage;
+        let now = env.block.time.seconds();
 
         let range_from = match last_value {
             Some(thing) => Some(Bound::Exclusive(Vec::from(thing.to_string()))),
@@ -306,8 +557,10 @@ impl OfferModel<'_> {
                 fiat_currency.to_string() + &*OfferState::Active.to_string(),
             ))
             .range(storage, range_from, None, Order::Ascending)
-            .take(limit as usize)
             .flat_map(|item| item.and_then(|(_, offer)| Ok(offer)))
+            .map(|offer| OfferModel::with_effective_state(storage, offer, now))
+            .filter(|offer| offer.state == OfferState::Active)
+            .take(limit as usize)
             .collect();
 
         Ok(result)
@@ -315,11 +568,13 @@ impl OfferModel<'_> {
 
     pub fn query_by_fiat(
         deps: Deps,
+        env: Env,
         fiat_currency: FiatCurrency,
         last_value: Option<u64>,
         limit: u32,
     ) -> StdResult<Vec<Offer>> {
         let storage = deps.storage;
+        let now = env.block.time.seconds();
 
         let range_from = match last_value {
             Some(thing) => Some(Bound::Exclusive(Vec::from(thing.to_string()))),
@@ -331,8 +586,10 @@ impl OfferModel<'_> {
             .fiat
             .prefix(fiat_currency.to_string())
             .range(storage, range_from, None, Order::Ascending)
-            .take(limit as usize)
             .flat_map(|item| item.and_then(|(_, offer)| Ok(offer)))
+            .filter(|offer| offer.state != OfferState::Archived)
+            .map(|offer| OfferModel::with_effective_state(storage, offer, now))
+            .take(limit as usize)
             .collect();
 
         Ok(result)
@@ -340,11 +597,13 @@ impl OfferModel<'_> {
 
     pub fn query(
         deps: Deps,
+        env: Env,
         owner: Option<Addr>,
         last_value: Option<u64>,
         limit: u32,
     ) -> StdResult<Vec<Offer>> {
         let storage = deps.storage;
+        let now = env.block.time.seconds();
         // let range: Box<dyn Iterator<Item = StdResult<Pair<Offer>>>>;
 
         let range_from = match last_value {
@@ -368,8 +627,10 @@ impl OfferModel<'_> {
         };
 
         let result = range
-            .take(limit as usize)
             .flat_map(|item| item.and_then(|(_, offer)| Ok(offer)))
+            .filter(|offer| offer.state != OfferState::Archived)
+            .map(|offer| OfferModel::with_effective_state(storage, offer, now))
+            .take(limit as usize)
             .collect();
 
         Ok(result)
@@ -383,13 +644,47 @@ pub struct TradeInfo {
     pub expired: bool,
 }
 
+/// Public profile of a trader, contacts sent to them must be encrypted with `encryption_key`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Profile {
+    pub addr: Addr,
+    pub encryption_key: Binary,
+    pub metadata: Option<String>,
+    pub signing_key: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Rating {
+    pub trade: Addr,
+    pub rater: Addr,
+    pub ratee: Addr,
+    pub rating: u8,
+    pub comment_hash: Option<String>,
+    pub timestamp: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct TradeAddr {
     pub trade: Addr,
+    pub offer_id: u64,
     pub seller: Addr,
     pub buyer: Addr,
     pub arbitrator: Addr,
     pub state: TradeState,
+    pub disputed_at: Option<u64>,
+    pub dispute_reason: Option<DisputeReason>,
+    pub dispute_deadline: Option<u64>,
+}
+
+/// How the buyer pays the seller, fiat unless set on the offer.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentMethod {
+    Fiat,
+    // Paid over a Lightning invoice, the preimage settles the trade
+    Lightning,
+    // Paid on-chain, the buyer releases the escrow with an SPV proof of the payment
+    Bitcoin,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -414,4 +709,5 @@ impl fmt::Display for OfferState {
 pub enum OfferState {
     Active,
     Paused,
+    Archived,
 }
@@ -1,32 +1,96 @@
-use cosmwasm_std::{Addr, Uint128};
+use crate::bitcoin::{BtcAddress, Sats};
+use cosmwasm_std::{Addr, Binary, Decimal, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt::{self};
+use terraswap::asset::AssetInfo;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub offer_id: u64,
-    pub ust_amount: String,
+    pub ust_amount: Uint128,
     pub taker: String,
     pub offers_addr: String,
-    pub arbitrator: String,
+    pub arbitrators: Vec<String>,
     pub taker_contact: String,
+    pub seed_commitment: Binary,
+    // sha256 hash picked by the buyer, required by HTLC offers
+    pub hashlock: Option<Binary>,
+    // Payment hash of the seller's Lightning invoice, required by Lightning offers
+    pub payment_hash: Option<Binary>,
+    // Amount the buyer pays on-chain, required by Bitcoin offers
+    pub btc_amount: Option<Sats>,
+    // Where the buyer wants the escrow paid out, only the buyer can set it
+    pub payout: Option<PayoutMsg>,
     pub timestamp: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    FundEscrow {},
+    /// Only the seller funds, `entropy` can only be given when the seller is the maker.
+    ///
+    /// On Bitcoin trades `btc_amount` confirms the sats the buyer offered to pay.
+    FundEscrow {
+        entropy: Option<Binary>,
+        btc_amount: Option<Sats>,
+    },
+    /// Maker's entropy for the arbitrator pick, the taker committed the seed.
+    ProvideEntropy {
+        entropy: Binary,
+    },
     Refund {},
     Release {},
-    Dispute {},
+    /// Release authorised off-chain by the seller, it can be relayed by anyone.
+    ///
+    /// `signature` is the seller's secp256k1 signature over
+    /// `sha256(trade address || chain id || nonce as 8 big-endian bytes)`, checked against
+    /// the signing key in the seller's profile. `nonce` has to match `TradeData::release_nonce`.
+    ReleaseWithSignature {
+        nonce: u64,
+        signature: Binary,
+    },
+    FiatDeposited {},
+    Dispute {
+        reason: DisputeReason,
+    },
+    AssignArbitrator {
+        seed: Option<Binary>,
+    },
+    Escalate {},
+    Appeal {},
+    ExecuteRuling {},
+    Claim {
+        preimage: Binary,
+    },
+    SettleLightning {
+        preimage: Binary,
+    },
+    /// SPV proof of the buyer's Bitcoin payment to the seller.
+    ///
+    /// `tx` is the raw transaction, `merkle_branch` its merkle path in `headers[0]` and
+    /// `headers` the raw 80 byte headers of the including block and the blocks built on it.
+    ProveBtcPayment {
+        tx: Binary,
+        merkle_branch: Vec<Binary>,
+        tx_index: u32,
+        headers: Vec<Binary>,
+    },
+    PostMessage {
+        kind: TradeMessageKind,
+        content: String,
+    },
+    /// Maker's contact, encrypted with the taker's profile encryption key.
+    SetMakerContact {
+        maker_contact: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     State {},
+    Messages {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -35,13 +99,113 @@ pub struct TradeData {
     pub factory_addr: Addr,
     pub buyer: Addr,
     pub seller: Addr,
+    // Encrypted with the maker's profile encryption key
     pub taker_contact: String,
+    // Encrypted with the taker's profile encryption key, set by the maker once the trade exists
+    pub maker_contact: Option<String>,
+    // Empty until the taker's seed and the maker's entropy pick one of the candidates
     pub arbitrator: Addr,
+    pub arbitrator_candidates: Vec<Addr>,
+    pub seed_commitment: Binary,
+    pub entropy: Option<Binary>,
     pub offer_contract: Addr,
     pub offer_id: u64,
     pub expire_height: u64,
     pub ust_amount: Uint128,
     pub state: TradeState,
+    // Time (in seconds) the escrow was funded
+    pub funded_at: Option<u64>,
+    pub disputer: Option<Addr>,
+    // Paid by the disputer, it goes to whoever the dispute is settled for
+    pub dispute_deposit: Uint128,
+    pub dispute_reason: Option<DisputeReason>,
+    // Time (in seconds) the dispute was opened
+    pub disputed_at: Option<u64>,
+    // Block height after which an unanswered dispute can be escalated
+    pub dispute_deadline: Option<u64>,
+    // Arbitrators that let the dispute deadline pass
+    pub dismissed_arbitrators: Vec<Addr>,
+    pub ruling: Option<Ruling>,
+    pub appellant: Option<Addr>,
+    pub appeal_bond: Uint128,
+    pub escrow_mode: EscrowMode,
+    // Parties that approved paying out the escrow in multisig mode
+    pub release_approvals: Vec<Addr>,
+    pub refund_approvals: Vec<Addr>,
+    // Expected nonce of the next signed release authorisation
+    pub release_nonce: u64,
+    pub htlc: Option<Htlc>,
+    pub payment_hash: Option<Binary>,
+    pub btc_payment: Option<BtcPayment>,
+    pub payout: Option<Payout>,
+}
+
+/// On-chain Bitcoin payment the buyer owes the seller.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BtcPayment {
+    pub address: BtcAddress,
+    pub sats: Sats,
+    // Transaction that paid it, set once the payment is proven
+    pub txid: Option<String>,
+}
+
+/// Payout the buyer asks for when opening the trade.
+///
+/// With an `ask_asset` the released UST is swapped on its Terraswap UST pair, bounded by
+/// `belief_price` and `max_spread`. If the swap fails the UST is sent as is.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PayoutMsg {
+    // Defaults to the buyer
+    pub address: Option<String>,
+    pub ask_asset: Option<AssetInfo>,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Payout {
+    pub address: Addr,
+    pub swap: Option<PayoutSwap>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PayoutSwap {
+    pub pair_addr: Addr,
+    pub ask_asset: AssetInfo,
+    pub belief_price: Option<Decimal>,
+    pub max_spread: Option<Decimal>,
+}
+
+/// Hash time lock of an `EscrowMode::Htlc` trade.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Htlc {
+    pub hashlock: Binary,
+    // Block height from which the seller can refund, the buyer has to claim before it
+    pub timelock: u64,
+    // Revealed by the claim, the seller needs it to claim the counterpart HTLC
+    pub preimage: Option<Binary>,
+}
+
+/// How the escrow is paid out, set on the offer.
+///
+/// In `Multisig` mode `Release` and `Refund` record an approval and only pay out once two of
+/// the buyer, the seller and the arbitrator approved the same outcome.
+/// In `Htlc` mode the buyer is paid by revealing the preimage of the hashlock with `Claim`,
+/// for atomic swaps against a Bitcoin HTLC locked to the same hash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowMode {
+    Standard,
+    Multisig,
+    Htlc,
+}
+
+/// Decision of the arbitrator, it's carried out once the appeal window is over.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Ruling {
+    // Whether the escrow goes to the buyer, otherwise it's refunded to the seller
+    pub release: bool,
+    pub appeal_deadline: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -51,11 +215,51 @@ pub enum TradeState {
     Closed,
     Created,
     EscrowFunded,
+    FiatDeposited,
     Disputed,
+    Appealed,
     SettledForMaker,
     SettledForTaker,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeReason {
+    PaymentNotReceived,
+    PaymentNotReleased,
+    WrongAmount,
+    FraudSuspicion,
+}
+
+/// Entry of the trade message log, `content` is encrypted to the recipients or is an evidence hash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TradeMessage {
+    pub sender: Addr,
+    pub kind: TradeMessageKind,
+    pub content: String,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeMessageKind {
+    Message,
+    Evidence,
+}
+
+impl TradeState {
+    /// Whether the trade has been settled one way or another and can't change state anymore.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            TradeState::Canceled
+                | TradeState::Closed
+                | TradeState::SettledForMaker
+                | TradeState::SettledForTaker
+        )
+    }
+}
+
 impl fmt::Display for TradeState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)